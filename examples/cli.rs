@@ -1,4 +1,4 @@
-use futures::{ StreamExt, stream };
+use futures::StreamExt;
 use nemembory_core::{ ModelProvider, NememboryAgent };
 use std::io::{ self, Write };
 
@@ -13,7 +13,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "cli_agent",
         task,
         ModelProvider::OpenRouter("anthropic/claude-haiku-4.5".to_string())
-    )
+    )?
         .create_working_directory("D:\\agents")
         .default_handlers()
         .default_hooks();
@@ -0,0 +1,34 @@
+use nemembory_core::{
+    NememboryAgent,
+    agent::{ MockResponse, MockRunnableAgent, MockScript, MockToolCall },
+};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // Script the model: first a web search, then a plain answer
+    let script = MockScript::new(
+        vec![
+            MockResponse::text("It is sunny in Toronto.").with_tool_call(
+                MockToolCall::new(
+                    "web_search",
+                    r#"{"query":"weather toronto"}"#,
+                    r#"[{"title":"Toronto weather","url":"https://example.com","description":"Sunny"}]"#
+                )
+            ),
+            MockResponse::text("You're welcome!")
+        ]
+    );
+
+    // No api key or network access is needed
+    let mock = MockRunnableAgent::new(script);
+    let prompts = mock.prompt_log();
+    let mut agent = NememboryAgent::from_runnable("mock_agent", Box::new(mock));
+
+    println!("{}", agent.run("What is the weather today?", 5).await?);
+    println!("{}", agent.run("Thanks", 5).await?);
+
+    println!("Prompts received: {:?}", prompts.lock().unwrap());
+    println!("History length: {}", agent.messages.len());
+
+    Ok(())
+}
@@ -8,33 +8,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let task = "You are a helpful assistant that can answer questions.".to_string();
 
     // Create a new NememboryAgent with Anthropic as the model provider
-    let agent = NememboryAgent::new("stream_agent", task, ModelProvider::Anthropic);
+    let mut agent = NememboryAgent::new("stream_agent", task, ModelProvider::Anthropic)?;
 
     // Run the agent with streaming enabled
     let prompt = "What is the temperature in the next couple of days in toronto";
     println!("Running agent with prompt: {}\n", prompt);
     println!("--- Streaming Response ---");
 
-    // Get the stream and pin it
-    let stream = agent.run_stream(prompt, 20);
-    tokio::pin!(stream);
+    // The stream borrows the agent until it is dropped
+    {
+        let mut stream = agent.run_stream(prompt, 20);
 
-    // Process each chunk as it arrives
-    while let Some(result) = stream.next().await {
-        match result {
-            Ok(text) => {
-                // Print each chunk immediately without newline
-                print!("{}", text);
-                // Flush stdout to ensure immediate display
-                io::stdout().flush()?;
-            }
-            Err(e) => {
-                eprintln!("\nError during streaming: {}", e);
+        // Process each chunk as it arrives
+        while let Some(result) = stream.next().await {
+            match result {
+                Ok(text) => {
+                    // Print each chunk immediately without newline
+                    print!("{}", text);
+                    // Flush stdout to ensure immediate display
+                    io::stdout().flush()?;
+                }
+                Err(e) => {
+                    eprintln!("\nError during streaming: {}", e);
+                }
             }
         }
     }
 
     println!("\n\n--- Stream Complete ---");
+    println!("Messages recorded: {}", agent.messages.len());
 
     Ok(())
 }
@@ -51,11 +51,10 @@ async fn main() -> anyhow::Result<()> {
                     Ok(0) => break, // EOF
                     Ok(_) => {
                         let msg = line.trim();
-                        if !msg.is_empty() {
-                             if let Err(e) = write.send(Message::Text(msg.to_string().into())).await {
-                                 eprintln!("Error sending message: {}", e);
-                                 break;
-                             }
+                        let text = Message::Text(msg.to_string().into());
+                        if !msg.is_empty() && let Err(e) = write.send(text).await {
+                            eprintln!("Error sending message: {}", e);
+                            break;
                         }
                         line.clear();
                     }
@@ -4,3 +4,64 @@ CREATE TABLE agents (
     display_name VARCHAR(100) NOT NULL,
     system_prompt TEXT NOT NULL
 );
+
+CREATE TABLE memories (
+    id BIGSERIAL PRIMARY KEY,
+    agent_name VARCHAR(100) NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Facts can be longer than a btree entry allows, so uniqueness is checked on their hash
+CREATE UNIQUE INDEX memories_agent_content_idx ON memories (agent_name, md5(content));
+
+CREATE INDEX memories_agent_name_idx ON memories (agent_name);
+
+CREATE TABLE conversations (
+    id SERIAL PRIMARY KEY,
+    agent_name VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE messages (
+    id BIGSERIAL PRIMARY KEY,
+    conversation_id INTEGER NOT NULL REFERENCES conversations (id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL,
+    content TEXT NOT NULL,
+    time_stamp TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX messages_conversation_id_idx ON messages (conversation_id);
+
+CREATE TABLE tools (
+    name VARCHAR(100) PRIMARY KEY,
+    description TEXT NOT NULL
+);
+
+INSERT INTO tools (name, description) VALUES
+    ('rest_api', 'Calls a RESTful endpoint'),
+    ('web_search', 'Searches the web'),
+    ('shell_tool', 'Executes shell commands on the host'),
+    ('shell_session', 'Runs commands in a persistent shell session on the host'),
+    ('link_to_markdown', 'Fetches a link and converts it to markdown'),
+    ('get_date', 'Returns the current date and time');
+
+CREATE TABLE agent_tools (
+    agent_id INTEGER NOT NULL REFERENCES agents (id) ON DELETE CASCADE,
+    tool_name VARCHAR(100) NOT NULL REFERENCES tools (name) ON DELETE CASCADE,
+    PRIMARY KEY (agent_id, tool_name)
+);
+
+CREATE TABLE usage (
+    id BIGSERIAL PRIMARY KEY,
+    conversation_id INTEGER NOT NULL REFERENCES conversations (id) ON DELETE CASCADE,
+    agent_name VARCHAR(100) NOT NULL,
+    model VARCHAR(200) NOT NULL,
+    turns INTEGER NOT NULL,
+    input_tokens BIGINT NOT NULL,
+    output_tokens BIGINT NOT NULL,
+    cost DOUBLE PRECISION,
+    time_stamp TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX usage_agent_name_idx ON usage (agent_name);
@@ -2,20 +2,37 @@ use async_trait::async_trait;
 use futures::{ Stream, StreamExt };
 use rig::{
     agent::MultiTurnStreamItem,
-    completion::{ CompletionModel, Prompt, PromptError },
-    streaming::StreamingChat,
+    completion::{ AssistantContent, CompletionModel, GetTokenUsage, Prompt, PromptError },
+    message::ToolResultContent,
+    streaming::{ StreamedAssistantContent, StreamedUserContent, StreamingPrompt },
 };
+use std::collections::HashMap;
+use std::future::Future;
 use std::pin::Pin;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
 use serde::{ Deserialize, Serialize };
 use std::sync::Arc;
-use crate::{ ModelProvider, agent::{ FileHandler, build_runnable_agent, hooks::LlmResponseHooks } };
-use crate::hooks::{
-    log_tool_call,
-    log_tool_call_result,
-    WriteToolLogToFile,
-    WriteToolResultToFile,
+use crate::{ ModelProvider, agent::{ FileHandler, hooks::LlmResponseHooks } };
+use crate::agent::model::{
+    AgentOptions,
+    ModelError,
+    build_runnable_agent_with_options,
+    model_id,
 };
+use crate::agent::usage::{ PriceTable, RunUsage, TokenUsage, UsageTracker };
+use crate::memory::{ MemoryStore, extract_facts, format_memories };
+use crate::agent::policy::ToolPolicies;
+use crate::agent::recall::SemanticRecall;
+use crate::agent::compaction::{ CompactionConfig, SUMMARY_PROMPT, apply_compaction };
+use crate::data::{ AgentPersistence, ConversationStore, DbError };
+use crate::handlers::DbMessageHandler;
+use crate::tools::{ Credentials, ToolRegistry };
+use crate::agent::builder::NememboryAgentBuilder;
+use crate::agent::events::{ AgentEvent, EventStream };
+use crate::hooks::{ ConsoleLogger, WriteToolLogToFile, WriteToolResultToFile };
 
 /// A boxed error type for streaming operations
 pub type StreamError = Box<dyn std::error::Error + Send + Sync>;
@@ -31,25 +48,110 @@ pub struct NememboryAgent {
     pub working_dir: Option<String>,
     pub has_working_dir: bool,
     pub hooks: Option<LlmResponseHooks>,
+    pub memory: Option<Arc<dyn MemoryStore>>,
+    pub memory_limit: usize,
+    pub recall: Option<SemanticRecall>,
+    pub compaction: Option<CompactionConfig>,
+    /// Model id used to price token usage
+    pub model: String,
+    pub usage: UsageTracker,
+    pub prices: PriceTable,
+    /// Wall-clock limit of a single run, including every tool call
+    pub run_timeout: Option<Duration>,
+    /// Policies applied to every tool call, see `NememboryAgentBuilder::policy`
+    pub tool_policies: ToolPolicies,
+    /// Credential profiles whose secrets are redacted from hook events and logs
+    pub credentials: Credentials,
 }
 
 impl NememboryAgent {
-    pub fn new(name: &str, task: String, model: ModelProvider) -> Self {
+    /// Fails when the model provider's client cannot be created, e.g. without its api key
+    pub fn new(name: &str, task: String, model: ModelProvider) -> Result<Self, ModelError> {
+        Self::builder(name, model).task(&task).build()
+    }
+
+    /// Starts a builder exposing model, max tokens, temperature, preamble and tool options
+    pub fn builder(name: &str, model: ModelProvider) -> NememboryAgentBuilder {
+        NememboryAgentBuilder::new(name, model)
+    }
+
+    /// Builds an agent with the named tools from `registry` instead of the default tool set
+    pub fn with_tools(
+        name: &str,
+        task: String,
+        model: ModelProvider,
+        registry: &ToolRegistry,
+        tools: &[String]
+    ) -> Result<Self, ModelError> {
+        let tools = tools
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        Self::builder(name, model).task(&task).registry(registry.clone()).tools(&tools).build()
+    }
+
+    /// Builds the agent stored under `code`, using its system prompt as the preamble and
+    /// attaching only the tools granted to it. An agent without grants gets no tools.
+    pub async fn from_stored_agent(
+        store: &(dyn AgentPersistence + Send + Sync),
+        code: &str,
+        model: ModelProvider,
+        registry: &ToolRegistry
+    ) -> Result<Self, DbError> {
+        let policies = ToolPolicies::new();
+        Self::from_stored_agent_with_policies(store, code, model, registry, policies).await
+    }
+
+    /// Like `from_stored_agent`, with `policies` applied to every tool call
+    pub async fn from_stored_agent_with_policies(
+        store: &(dyn AgentPersistence + Send + Sync),
+        code: &str,
+        model: ModelProvider,
+        registry: &ToolRegistry,
+        policies: ToolPolicies
+    ) -> Result<Self, DbError> {
+        let record = store.get_agent_by_code(code).await?;
+        let mut options = AgentOptions::new(record.system_prompt);
+        options.tools = store
+            .get_agent_tools(code).await?
+            .into_iter()
+            .map(|tool| tool.name)
+            .collect::<Vec<String>>();
+        options.policies = policies;
+        let model_id = model_id(&model, &options);
+        let agent = build_runnable_agent_with_options(model, &options, registry)?;
+        let mut agent = Self::from_runnable(&record.code, agent);
+        agent.model = model_id;
+        agent.tool_policies = options.policies;
+        Ok(agent)
+    }
+
+    pub fn from_runnable(name: &str, agent: Box<dyn RunnableAgent>) -> Self {
         Self {
             hooks: None,
+            memory: None,
+            memory_limit: 5,
+            recall: None,
+            compaction: None,
+            model: String::new(),
+            usage: UsageTracker::new(),
+            prices: PriceTable::new(),
+            run_timeout: None,
+            tool_policies: ToolPolicies::new(),
+            credentials: Credentials::new(),
             working_dir: None,
             has_working_dir: false,
             name: name.to_owned(),
             messages: Vec::new(),
             message_handlers: Vec::new(),
-            agent: build_runnable_agent(model, task.to_string()),
+            agent,
         }
     }
 
     pub fn create_working_directory(mut self, working_dir: &str) -> Self {
         self.working_dir = Some(working_dir.to_string());
         if let Err(result) = std::fs::DirBuilder::new().recursive(true).create(working_dir) {
-            panic!("Unable to create agent directory {}", result.to_string());
+            panic!("Unable to create agent directory {}", result);
         }
         self.has_working_dir = true;
         self
@@ -65,12 +167,63 @@ impl NememboryAgent {
         self
     }
 
+    /// Attaches a long-term memory store. Up to `limit` relevant facts are recalled
+    /// into the prompt before each run, and facts from each exchange are stored after it.
+    pub fn with_memory(mut self, store: Arc<dyn MemoryStore>, limit: usize) -> Self {
+        self.memory = Some(store);
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Sends only the semantically relevant past messages to the model instead of the full history
+    pub fn with_semantic_recall(mut self, recall: SemanticRecall) -> Self {
+        self.recall = Some(recall);
+        self
+    }
+
+    /// Summarizes the oldest messages once the history grows past the configured token budget
+    pub fn with_compaction(mut self, compaction: CompactionConfig) -> Self {
+        self.compaction = Some(compaction);
+        self
+    }
+
+    /// Loads the history of a stored conversation, compacted as it was when last used, and
+    /// persists every new message to it
+    pub async fn resume_conversation(
+        mut self,
+        store: Arc<dyn ConversationStore + Send + Sync>,
+        conversation_id: i32
+    ) -> Result<Self, DbError> {
+        self.messages = apply_compaction(store.load_messages(conversation_id).await?);
+        self.message_handlers.push(Arc::new(DbMessageHandler::new(store, conversation_id)));
+        Ok(self)
+    }
+
+    /// Prices used to compute the spend of each run and of the session
+    pub fn with_prices(mut self, prices: PriceTable) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    /// Redacts the secrets of `credentials` from hook events. The agent's `rest_api` tool
+    /// must be built with the same credentials, see `NememboryAgentBuilder::credentials`.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Aborts runs that take longer than `timeout`
+    pub fn with_run_timeout(mut self, timeout: Duration) -> Self {
+        self.run_timeout = Some(timeout);
+        self
+    }
+
     pub fn default_handlers(mut self) -> Self {
         let has_dir = self.has_working_dir.to_owned();
         if has_dir {
             let working_dir = &self.working_dir.as_ref().unwrap();
             let chat_log_handler = Arc::new(
-                FileHandler::new(format!("{}/{}", working_dir, "chat.log".to_string()))
+                FileHandler::new(format!("{}/{}", working_dir, "chat.log"))
             );
             self.message_handlers.push(chat_log_handler);
         }
@@ -83,87 +236,352 @@ impl NememboryAgent {
         let has_dir = self.has_working_dir.to_owned();
         if has_dir {
             let working_dir = &self.working_dir.as_ref().unwrap();
-            let path = format!("{}/{}", working_dir, "tool.log".to_string());
-            let file_handler = WriteToolLogToFile::new(&path);
-            let result_path = format!("{}/{}", working_dir, "tool_result.log".to_string());
-            let result_file_handler = WriteToolResultToFile::new(&result_path);
-            let mut hooks = self.hooks.take().unwrap_or_else(LlmResponseHooks::new);
-            hooks.add_tool_call_callback(move |params| file_handler.write_to_file(params));
-            hooks.add_tool_call_result_callback(move |params|
-                result_file_handler.write_to_file(params)
-            );
+            let path = format!("{}/{}", working_dir, "tool.log");
+            let result_path = format!("{}/{}", working_dir, "tool_result.log");
+            let mut hooks = self.hooks.take().unwrap_or_default();
+            hooks.add_observer(Arc::new(WriteToolLogToFile::new(&path)));
+            hooks.add_observer(Arc::new(WriteToolResultToFile::new(&result_path)));
             self.hooks = Some(hooks);
         }
         self
     }
 
     pub async fn run(&mut self, prompt: &str, max_turns: usize) -> Result<String, std::io::Error> {
-        let messages = self.messages
-            .clone()
-            .into_iter()
-            .map(|m| m.into())
-            .collect::<Vec<rig::message::Message>>();
+        self.run_with_cancel(prompt, max_turns, CancellationToken::new()).await
+    }
+
+    /// Like `run`, but stops as soon as `cancel` is cancelled. Cancelled runs fail with
+    /// `ErrorKind::Interrupted`, runs exceeding `run_timeout` with `ErrorKind::TimedOut`;
+    /// neither is added to the history.
+    pub async fn run_with_cancel(
+        &mut self,
+        prompt: &str,
+        max_turns: usize,
+        cancel: CancellationToken
+    ) -> Result<String, std::io::Error> {
+        self.compact_history().await;
+        let augmented_prompt = self.recall_memories(prompt).await;
+        let messages = self.history_for(prompt).await;
+        let mut hooks = self.run_hooks();
+        hooks.set_cancellation(cancel.clone());
+
+        let run = self.agent.run(&augmented_prompt, &messages, max_turns, &hooks);
+        let result = tokio::select! {
+            _ = cancel.cancelled() => Err(RunAbort::Cancelled.into()),
+            result = with_timeout(self.run_timeout, run) => match result {
+                Some(Ok(result)) => Ok(result),
+                Some(Err(e)) => match self.tool_policies.aborted() {
+                    Some(reason) => Err(RunAbort::Policy(reason).into()),
+                    None => Err(std::io::Error::other(format!("Agent run failed: {}", e))),
+                },
+                None => Err(RunAbort::TimedOut(self.run_timeout.unwrap_or_default()).into()),
+            },
+        };
+        self.finish_usage().await;
+
+        if let Ok(result) = &result {
+            self.record_exchange(prompt, result).await;
+        }
+        result
+    }
 
-        let mut hooks = self.hooks.clone().unwrap_or_else(LlmResponseHooks::new);
-        hooks.add_tool_call_callback(log_tool_call);
-        hooks.add_tool_call_result_callback(log_tool_call_result);
+    /// Streams the response text while running the full multi-turn tool loop with the same
+    /// hooks as `run`. Once the stream completes without error, the exchange is added to `messages`.
+    pub fn run_stream(&mut self, prompt: &str, max_turns: usize) -> AgentStream<'_> {
+        self.run_stream_with_cancel(prompt, max_turns, CancellationToken::new())
+    }
+
+    /// Like `run_stream`, but ends with an error as soon as `cancel` is cancelled
+    pub fn run_stream_with_cancel(
+        &mut self,
+        prompt: &str,
+        max_turns: usize,
+        cancel: CancellationToken
+    ) -> AgentStream<'_> {
+        Box::pin(
+            self.run_events_with_cancel(prompt, max_turns, cancel).filter_map(|event| async move {
+                match event {
+                    AgentEvent::TextDelta { text } => Some(Ok(text)),
+                    AgentEvent::Error { message } => Some(Err(StreamError::from(message))),
+                    _ => None,
+                }
+            })
+        )
+    }
 
-        match self.agent.run(prompt, &messages, max_turns, &hooks).await {
-            Ok(result) => {
-                self.add_message(Message::new(MessageRole::User, prompt.to_string())).await;
-                self.add_message(Message::new(MessageRole::Assistant, result.clone())).await;
-                Ok(result)
+    /// Like `run_stream`, but yields every `AgentEvent` (tool calls, tool results, reasoning,
+    /// usage and the final response) so UIs can render tool activity live
+    pub fn run_events(&mut self, prompt: &str, max_turns: usize) -> EventStream<'_> {
+        self.run_events_with_cancel(prompt, max_turns, CancellationToken::new())
+    }
+
+    /// Like `run_events`, but ends with an `AgentEvent::Error` as soon as `cancel` is
+    /// cancelled or the run exceeds `run_timeout`
+    pub fn run_events_with_cancel(
+        &mut self,
+        prompt: &str,
+        max_turns: usize,
+        cancel: CancellationToken
+    ) -> EventStream<'_> {
+        let prompt = prompt.to_string();
+        let deadline = self.run_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+
+        Box::pin(
+            async_stream::stream! {
+                self.compact_history().await;
+                let messages = self.history_for(&prompt).await;
+                let augmented_prompt = self.recall_memories(&prompt).await;
+                let mut hooks = self.run_hooks();
+                hooks.set_cancellation(cancel.clone());
+
+                let mut streamed_text = String::new();
+                let mut final_response = None;
+                let mut failed = false;
+                {
+                    let mut events = self.agent.run_events(
+                        &augmented_prompt,
+                        &messages,
+                        max_turns,
+                        &hooks
+                    );
+                    loop {
+                        let next = tokio::select! {
+                            _ = cancel.cancelled() => Err(RunAbort::Cancelled),
+                            next = with_deadline(deadline, events.next()) => {
+                                next.ok_or(RunAbort::TimedOut(self.run_timeout.unwrap_or_default()))
+                            }
+                        };
+                        let event = match next {
+                            Ok(Some(event)) => event,
+                            Ok(None) => break,
+                            Err(abort) => {
+                                failed = true;
+                                yield AgentEvent::Error { message: abort.to_string() };
+                                break;
+                            }
+                        };
+                        let event = match (event, self.tool_policies.aborted()) {
+                            (AgentEvent::Error { .. }, Some(reason)) => AgentEvent::Error {
+                                message: RunAbort::Policy(reason).to_string(),
+                            },
+                            (event, _) => event,
+                        };
+                        match &event {
+                            AgentEvent::TextDelta { text } => streamed_text.push_str(text),
+                            AgentEvent::Final { response } => {
+                                final_response = Some(response.clone());
+                            }
+                            AgentEvent::Error { .. } => {
+                                failed = true;
+                            }
+                            AgentEvent::Usage { input_tokens, output_tokens } => {
+                                let total = TokenUsage::new(*input_tokens, *output_tokens);
+                                hooks.record_remaining_usage(total);
+                            }
+                            _ => {}
+                        }
+                        yield event;
+                    }
+                }
+
+                self.finish_usage().await;
+                if !failed {
+                    let response = final_response.unwrap_or(streamed_text);
+                    self.record_exchange(&prompt, &response).await;
+                }
+            }
+        )
+    }
+
+    /// Hooks for a single run: the configured hooks plus console logging of tool activity
+    fn run_hooks(&self) -> LlmResponseHooks {
+        let mut hooks = self.hooks
+            .as_ref()
+            .map(|hooks| hooks.for_run(&self.name))
+            .unwrap_or_else(|| LlmResponseHooks::new().for_run(&self.name));
+        hooks.add_observer(Arc::new(ConsoleLogger));
+        hooks.set_usage_tracker(self.usage.clone());
+        self.tool_policies.reset();
+        hooks.set_policies(self.tool_policies.clone());
+        hooks.set_credentials(self.credentials.clone());
+        hooks
+    }
+
+    /// Closes the usage of the current run and hands it to the message handlers
+    async fn finish_usage(&self) {
+        let run_usage = self.usage.finish_run(&self.name, &self.model, &self.prices);
+        for handler in &self.message_handlers {
+            if let Err(e) = handler.handle_usage(run_usage.clone()).await {
+                warn!(agent = %self.name, "error handling usage: {}", e);
             }
-            Err(e) =>
-                Err(
-                    std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Agent run failed: {}", e)
-                    )
-                ),
         }
     }
 
-    pub fn run_stream(&self, prompt: &str, max_turns: usize) -> AgentStream<'_> {
-        let messages = self.messages
+    /// Appends a completed exchange to the history and long-term memory
+    async fn record_exchange(&mut self, prompt: &str, response: &str) {
+        self.add_message(Message::new(MessageRole::User, prompt.to_string())).await;
+        self.add_message(Message::new(MessageRole::Assistant, response.to_string())).await;
+        self.remember_exchange(prompt, response).await;
+    }
+
+    /// Replaces the oldest messages with a model-written summary when the history exceeds
+    /// the compaction budget. The summary is written without tools, its usage is reported as
+    /// a run of its own and the summary is handed to the message handlers. Failures leave the
+    /// history untouched.
+    pub async fn compact_history(&mut self) {
+        let Some(split) = self.compaction.as_ref().and_then(|c| c.split_point(&self.messages)) else {
+            return;
+        };
+
+        let older = self.messages[..split]
             .iter()
             .map(|m| m.clone().into())
             .collect::<Vec<rig::message::Message>>();
 
-        self.agent.run_stream(prompt, &messages, max_turns)
+        match self.agent.summarize(&older).await {
+            Ok((summary, usage)) => {
+                self.usage.record_turn(usage);
+                self.finish_usage().await;
+                // Stored with the timestamp of the first kept message, see `apply_compaction`
+                let mut summary = Message::new(MessageRole::Summary, summary);
+                summary.time_stamp = self.messages
+                    .get(split)
+                    .map(|m| m.time_stamp)
+                    .unwrap_or_else(chrono::Utc::now);
+                self.messages.drain(..split);
+                self.messages.insert(0, summary.clone());
+                self.notify_handlers(&summary).await;
+                if let Some(recall) = &self.recall {
+                    recall.reset().await;
+                }
+            }
+            Err(e) => warn!(agent = %self.name, "error compacting history: {}", e),
+        }
+    }
+
+    /// Returns the history sent to the model: the relevant subset when semantic recall is
+    /// enabled, otherwise every message
+    async fn history_for(&self, prompt: &str) -> Vec<rig::message::Message> {
+        let messages = match &self.recall {
+            Some(recall) =>
+                recall.select(&self.messages, prompt).await.unwrap_or_else(|e| {
+                    warn!(agent = %self.name, "error selecting relevant messages: {}", e);
+                    self.messages.clone()
+                }),
+            None => self.messages.clone(),
+        };
+
+        messages
+            .into_iter()
+            .map(|m| m.into())
+            .collect::<Vec<rig::message::Message>>()
+    }
+
+    /// Prepends the facts recalled from long-term memory to the prompt
+    async fn recall_memories(&self, prompt: &str) -> String {
+        let Some(memory) = &self.memory else {
+            return prompt.to_string();
+        };
+
+        match memory.recall(&self.name, prompt, self.memory_limit).await {
+            Ok(facts) => format_memories(&facts, prompt),
+            Err(e) => {
+                warn!(agent = %self.name, "error recalling memories: {}", e);
+                prompt.to_string()
+            }
+        }
+    }
+
+    /// Stores the facts extracted from a completed exchange in long-term memory
+    async fn remember_exchange(&self, prompt: &str, response: &str) {
+        let Some(memory) = &self.memory else {
+            return;
+        };
+
+        for fact in extract_facts(prompt, response) {
+            if let Err(e) = memory.remember(&self.name, &fact).await {
+                warn!(agent = %self.name, "error storing memory: {}", e);
+            }
+        }
     }
 
     pub async fn add_message(&mut self, message: Message) {
         self.messages.push(message.clone());
+        self.notify_handlers(&message).await;
+    }
 
-        //todo: move this to a seperate function
+    async fn notify_handlers(&self, message: &Message) {
         for handler in &self.message_handlers {
             let handler = Arc::clone(handler);
             if let Err(e) = handler.handle_message(message.clone()).await {
                 // log the error but continue processing other handlers
-                eprintln!("Error handling message: {}", e);
-                continue;
+                warn!(agent = %self.name, "error handling message: {}", e);
             }
         }
     }
 }
 
+/// Why a run stopped before the model finished
+#[derive(Debug, thiserror::Error)]
+pub enum RunAbort {
+    #[error("Agent run cancelled")] Cancelled,
+    #[error("Agent run timed out after {0:?}")] TimedOut(Duration),
+    #[error("Agent run aborted by policy: {0}")] Policy(String),
+}
+
+impl From<RunAbort> for std::io::Error {
+    fn from(abort: RunAbort) -> Self {
+        let kind = match abort {
+            RunAbort::Cancelled => std::io::ErrorKind::Interrupted,
+            RunAbort::TimedOut(_) => std::io::ErrorKind::TimedOut,
+            RunAbort::Policy(_) => std::io::ErrorKind::PermissionDenied,
+        };
+        std::io::Error::new(kind, abort)
+    }
+}
+
+/// Awaits `future`, returning `None` when `timeout` elapses first
+async fn with_timeout<F: Future>(timeout: Option<Duration>, future: F) -> Option<F::Output> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+async fn with_deadline<F: Future>(
+    deadline: Option<tokio::time::Instant>,
+    future: F
+) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
 #[async_trait]
 pub trait RunnableAgent: Send + Sync {
     async fn run(
         &self,
         prompt: &str,
-        messages: &Vec<rig::message::Message>,
+        messages: &[rig::message::Message],
         max_turns: usize,
         nemembory_hook: &LlmResponseHooks
     ) -> Result<String, PromptError>;
 
-    fn run_stream(
+    fn run_events(
         &self,
         prompt: &str,
-        messages: &Vec<rig::message::Message>,
-        max_turns: usize
-    ) -> AgentStream<'_>;
+        messages: &[rig::message::Message],
+        max_turns: usize,
+        nemembory_hook: &LlmResponseHooks
+    ) -> EventStream<'_>;
+
+    /// Summarizes `messages` in a single completion without the agent's preamble or tools,
+    /// returning the summary and its token usage
+    async fn summarize(
+        &self,
+        messages: &[rig::message::Message]
+    ) -> Result<(String, TokenUsage), PromptError>;
 }
 
 #[async_trait]
@@ -171,63 +589,149 @@ impl<M: CompletionModel + Send + Sync + 'static> RunnableAgent for rig::agent::A
     async fn run(
         &self,
         prompt: &str,
-        messages: &Vec<rig::message::Message>,
+        messages: &[rig::message::Message],
         max_turns: usize,
         nemembory_hook: &LlmResponseHooks
     ) -> Result<String, PromptError> {
         self
             .prompt(prompt)
             .with_hook(nemembory_hook.clone())
-            .with_history(&mut messages.clone())
+            .with_history(&mut messages.to_vec())
             .multi_turn(max_turns).await
     }
 
-    fn run_stream(
+    async fn summarize(
+        &self,
+        messages: &[rig::message::Message]
+    ) -> Result<(String, TokenUsage), PromptError> {
+        let response = self.model
+            .completion_request(SUMMARY_PROMPT)
+            .messages(messages.to_vec())
+            .max_tokens_opt(self.max_tokens)
+            .send().await?;
+        let summary = response.choice
+            .iter()
+            .filter_map(|content| {
+                match content {
+                    AssistantContent::Text(text) => Some(text.text.clone()),
+                    _ => None,
+                }
+            })
+            .collect::<String>();
+        Ok((summary, response.usage.into()))
+    }
+
+    fn run_events(
         &self,
         prompt: &str,
-        messages: &Vec<rig::message::Message>,
-        _max_turns: usize
-    ) -> AgentStream<'_> {
+        messages: &[rig::message::Message],
+        max_turns: usize,
+        nemembory_hook: &LlmResponseHooks
+    ) -> EventStream<'_> {
         let messages = messages.to_vec();
         let prompt = prompt.to_string();
+        let hook = nemembory_hook.clone();
 
         Box::pin(
             async_stream::stream! {
-                let mut stream = self.stream_chat(&prompt, messages).await;
+                // rig 0.26 panics when a streaming hook is set and the history is empty, so the
+                // hook is called here as items arrive. rig only runs a requested tool once its
+                // call has been consumed, so policies still decide before the tool runs.
+                let mut stream = self
+                    .stream_prompt(prompt)
+                    .with_history(messages)
+                    .multi_turn(max_turns).await;
+                let mut in_turn = false;
+                let mut turn_text = String::new();
+                let mut calls: HashMap<String, (String, String)> = HashMap::new();
 
                 while let Some(result) = stream.next().await {
-                    match result {
-                        Ok(multi) => {
-                            match multi {
-                                MultiTurnStreamItem::StreamAssistantItem(
-                                    streamed_assistant_content,
-                                ) => {
-                                    match streamed_assistant_content {
-                                        rig::streaming::StreamedAssistantContent::Text(text) => {
-                                            yield Ok(text.text().to_string());
-                                        }
-                                        rig::streaming::StreamedAssistantContent::ToolCall(tc) => {
-                                            println!("Tool call {:?}", tc);
-                                        }
-                                        rig::streaming::StreamedAssistantContent::ToolCallDelta {
-                                            ..
-                                        } => {}
-                                        rig::streaming::StreamedAssistantContent::Reasoning(
-                                            reas,
-                                        ) => {
-                                            println!("Reasoning {:?}", reas);
-                                        }
-                                        rig::streaming::StreamedAssistantContent::Final(_) => {}
-                                    }
+                    let multi = match result {
+                        Ok(multi) => multi,
+                        Err(err) => {
+                            yield AgentEvent::Error { message: err.to_string() };
+                            continue;
+                        }
+                    };
+                    if let MultiTurnStreamItem::StreamAssistantItem(_) = &multi && !in_turn {
+                        in_turn = true;
+                        hook.start_turn();
+                    }
+                    match multi {
+                        MultiTurnStreamItem::StreamAssistantItem(streamed_assistant_content) => {
+                            match streamed_assistant_content {
+                                StreamedAssistantContent::Text(text) => {
+                                    turn_text.push_str(text.text());
+                                    yield AgentEvent::TextDelta {
+                                        text: text.text().to_string(),
+                                    };
+                                }
+                                StreamedAssistantContent::ToolCall(tc) => {
+                                    let name = tc.function.name;
+                                    let args = tc.function.arguments.to_string();
+                                    yield AgentEvent::ToolCallStarted {
+                                        id: tc.id.clone(),
+                                        name: name.clone(),
+                                        args: args.clone(),
+                                    };
+                                    let effective = hook.decide_tool_call(&name, &args).await;
+                                    hook.notify_tool_call(&name, &effective).await;
+                                    calls.insert(tc.id, (name, args));
+                                }
+                                StreamedAssistantContent::ToolCallDelta { id, delta } => {
+                                    yield AgentEvent::ToolCallArgsDelta { id, delta };
+                                }
+                                StreamedAssistantContent::Reasoning(reasoning) => {
+                                    yield AgentEvent::Reasoning {
+                                        text: reasoning.reasoning.join(""),
+                                    };
+                                }
+                                StreamedAssistantContent::Final(response) => {
+                                    let usage = response
+                                        .token_usage()
+                                        .map(TokenUsage::from)
+                                        .unwrap_or_default();
+                                    let content = std::mem::take(&mut turn_text);
+                                    hook.finish_streamed_turn(&content, usage).await;
                                 }
-                                MultiTurnStreamItem::StreamUserItem(_) => {}
-                                MultiTurnStreamItem::FinalResponse(_) => {}
-                                _ => {}
                             }
                         }
-                        Err(err) => {
-                            yield Err(Box::new(err) as Box<dyn std::error::Error + Send + Sync>);
+                        MultiTurnStreamItem::StreamUserItem(
+                            StreamedUserContent::ToolResult(tool_result),
+                        ) => {
+                            in_turn = false;
+                            let result = tool_result.content
+                                .iter()
+                                .map(|content| {
+                                    match content {
+                                        ToolResultContent::Text(text) => text.text.clone(),
+                                        _ => String::new(),
+                                    }
+                                })
+                                .collect::<String>();
+                            if let Some((name, args)) = calls.remove(&tool_result.id) {
+                                let args = hook.effective_args(&name, &args);
+                                hook.notify_tool_result(&name, &args, &result).await;
+                            }
+                            yield AgentEvent::ToolResult { id: tool_result.id, result };
+                            if let Some(reason) = hook.policies.aborted() {
+                                yield AgentEvent::Error {
+                                    message: RunAbort::Policy(reason).to_string(),
+                                };
+                                break;
+                            }
+                        }
+                        MultiTurnStreamItem::FinalResponse(final_response) => {
+                            let usage = final_response.usage();
+                            yield AgentEvent::Usage {
+                                input_tokens: usage.input_tokens,
+                                output_tokens: usage.output_tokens,
+                            };
+                            yield AgentEvent::Final {
+                                response: final_response.response().to_string(),
+                            };
                         }
+                        _ => {}
                     }
                 }
             }
@@ -256,9 +760,16 @@ impl Message {
 pub enum MessageRole {
     User,
     Assistant,
+    /// Model-written summary that replaces compacted history
+    Summary,
 }
 
 #[async_trait]
 pub trait MessageHandler {
     async fn handle_message(&self, message: Message) -> Result<(), std::io::Error>;
+
+    /// Called once per run with its token usage and cost. Ignored by default.
+    async fn handle_usage(&self, _usage: RunUsage) -> Result<(), std::io::Error> {
+        Ok(())
+    }
 }
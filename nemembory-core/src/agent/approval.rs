@@ -0,0 +1,324 @@
+use async_trait::async_trait;
+use serde::{ Deserialize, Serialize };
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::future::Future;
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use tokio::sync::{ mpsc, oneshot };
+use tokio_util::sync::CancellationToken;
+
+use crate::agent::policy::{ ToolCall, ToolDecision, ToolPolicy };
+
+/// A tool call waiting for a human decision
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ApprovalRequest {
+    pub id: u64,
+    pub tool_name: String,
+    pub args: serde_json::Value,
+}
+
+/// An approval request together with the channel its answer goes back on. Every
+/// connected approver gets a copy; the first answer wins.
+pub struct PendingApproval {
+    pub request: ApprovalRequest,
+    reply: Arc<Mutex<Option<oneshot::Sender<ToolDecision>>>>,
+    /// Cancelled once the tool call no longer waits for an answer
+    settled: CancellationToken,
+}
+
+impl PendingApproval {
+    /// Completes once any approver answered, the request timed out or the run stopped
+    /// waiting, so approvers can drop their copy
+    pub fn settled(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.settled.clone().cancelled_owned()
+    }
+
+    pub fn approve(self) {
+        self.respond(ToolDecision::Approve);
+    }
+
+    pub fn reject(self, reason: &str) {
+        self.respond(ToolDecision::Deny(reason.to_string()));
+    }
+
+    pub fn respond(self, decision: ToolDecision) {
+        // Another approver may have answered, or the run was cancelled in the meantime
+        if let Some(reply) = self.reply.lock().unwrap().take() {
+            let _ = reply.send(decision);
+        }
+    }
+}
+
+/// An approver attached with `ApprovalPolicy::connect`
+pub struct Approver {
+    /// Identifies the approver to `ApprovalPolicy::disconnect`
+    pub id: u64,
+    pub requests: mpsc::Receiver<PendingApproval>,
+}
+
+type RequiresApproval = Arc<dyn Fn(&ToolCall) -> bool + Send + Sync>;
+
+/// Connected approvers with their id, used to disconnect them
+type Approvers = Arc<Mutex<Vec<(u64, mpsc::Sender<PendingApproval>)>>>;
+
+/// Pauses sensitive tool calls until an approver answers. Approvers attach with
+/// `connect` and each receives every `PendingApproval`; without one, sensitive calls
+/// are denied.
+#[derive(Clone)]
+pub struct ApprovalPolicy {
+    requires_approval: RequiresApproval,
+    approvers: Approvers,
+    next_id: Arc<AtomicU64>,
+    next_approver: Arc<AtomicU64>,
+    timeout: Option<Duration>,
+}
+
+impl ApprovalPolicy {
+    pub fn new<F>(requires_approval: F) -> Self
+        where F: Fn(&ToolCall) -> bool + Send + Sync + 'static
+    {
+        Self {
+            requires_approval: Arc::new(requires_approval),
+            approvers: Arc::new(Mutex::new(Vec::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+            next_approver: Arc::new(AtomicU64::new(1)),
+            timeout: None,
+        }
+    }
+
+    /// Requires approval for `shell_tool`, for starting and running commands in a
+    /// `shell_session`, and for `rest_api` calls and OpenAPI operations that are not reads
+    pub fn sensitive_tools() -> Self {
+        Self::new(|call| {
+            let is_write = |method: &str| !matches!(method.to_uppercase().as_str(), "GET" | "HEAD");
+            match call.tool_name.as_str() {
+                "shell_tool" => true,
+                "shell_session" => matches!(call.args["action"].as_str(), Some("start" | "exec")),
+                "rest_api" => is_write(call.args["method"].as_str().unwrap_or("GET")),
+                _ => call.http_method.as_deref().is_some_and(is_write),
+            }
+        })
+    }
+
+    /// Denies requests left unanswered for longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Adds an approver next to the connected ones
+    pub fn connect(&self) -> Approver {
+        let (sender, requests) = mpsc::channel(16);
+        let id = self.next_approver.fetch_add(1, Ordering::SeqCst);
+        self.approvers.lock().unwrap().push((id, sender));
+        Approver { id, requests }
+    }
+
+    pub fn disconnect(&self, id: u64) {
+        self.approvers.lock().unwrap().retain(|(approver, _)| *approver != id);
+    }
+}
+
+#[async_trait]
+impl ToolPolicy for ApprovalPolicy {
+    async fn decide(&self, call: &ToolCall) -> ToolDecision {
+        if !(self.requires_approval)(call) {
+            return ToolDecision::Approve;
+        }
+        let approvers = {
+            let mut approvers = self.approvers.lock().unwrap();
+            approvers.retain(|(_, sender)| !sender.is_closed());
+            approvers
+                .iter()
+                .map(|(_, sender)| sender.clone())
+                .collect::<Vec<mpsc::Sender<PendingApproval>>>()
+        };
+        if approvers.is_empty() {
+            return ToolDecision::Deny("no approver is connected".to_string());
+        }
+
+        let (reply, answer) = oneshot::channel();
+        let reply = Arc::new(Mutex::new(Some(reply)));
+        let settled = CancellationToken::new();
+        let _settle = settled.clone().drop_guard();
+        let request = ApprovalRequest {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            tool_name: call.tool_name.clone(),
+            args: call.args.clone(),
+        };
+        let mut delivered = false;
+        for approver in approvers {
+            let pending = PendingApproval {
+                request: request.clone(),
+                reply: reply.clone(),
+                settled: settled.clone(),
+            };
+            delivered |= approver.send(pending).await.is_ok();
+        }
+        // Once every copy is dropped unanswered, `answer` fails and the call is denied
+        drop(reply);
+        if !delivered {
+            return ToolDecision::Deny("the approver disconnected".to_string());
+        }
+
+        let answer = match self.timeout {
+            Some(timeout) =>
+                match tokio::time::timeout(timeout, answer).await {
+                    Ok(answer) => answer,
+                    Err(_) => {
+                        return ToolDecision::Deny("approval timed out".to_string());
+                    }
+                }
+            None => answer.await,
+        };
+        answer.unwrap_or_else(|_| ToolDecision::Deny("the approver disconnected".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(tool_name: &str, args: serde_json::Value) -> ToolCall {
+        ToolCall { tool_name: tool_name.to_string(), args, http_method: None }
+    }
+
+    fn is_denied(decision: &ToolDecision) -> bool {
+        matches!(decision, ToolDecision::Deny(_))
+    }
+
+    #[tokio::test]
+    async fn reads_need_no_approval() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let get = call("rest_api", json!({ "url": "https://example.com" }));
+        assert!(matches!(policy.decide(&get).await, ToolDecision::Approve));
+        let search = call("web_search", json!({ "query": "rust" }));
+        assert!(matches!(policy.decide(&search).await, ToolDecision::Approve));
+    }
+
+    #[tokio::test]
+    async fn sensitive_calls_are_denied_without_approver() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let shell = call("shell_tool", json!({ "command": "ls" }));
+        assert!(is_denied(&policy.decide(&shell).await));
+        let delete = call("rest_api", json!({ "url": "https://example.com", "method": "delete" }));
+        assert!(is_denied(&policy.decide(&delete).await));
+    }
+
+    #[tokio::test]
+    async fn openapi_writes_need_approval() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let mut delete = call("deletePet", json!({ "petId": 1 }));
+        delete.http_method = Some("DELETE".to_string());
+        assert!(is_denied(&policy.decide(&delete).await));
+        let mut get = call("getPet", json!({ "petId": 1 }));
+        get.http_method = Some("GET".to_string());
+        assert!(matches!(policy.decide(&get).await, ToolDecision::Approve));
+    }
+
+    #[tokio::test]
+    async fn shell_session_commands_go_to_the_approver() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let mut approver = policy.connect();
+
+        let exec = json!({ "action": "exec", "session_id": "session-1", "command": "ls" });
+        let decision = tokio::spawn({
+            let policy = policy.clone();
+            async move { policy.decide(&call("shell_session", exec)).await }
+        });
+        let pending = approver.requests.recv().await.unwrap();
+        assert_eq!(pending.request.tool_name, "shell_session");
+        assert_eq!(pending.request.args["command"], "ls");
+        pending.reject("not now");
+        assert!(is_denied(&decision.await.unwrap()));
+
+        let close = call("shell_session", json!({ "action": "close", "session_id": "session-1" }));
+        assert!(matches!(policy.decide(&close).await, ToolDecision::Approve));
+    }
+
+    #[tokio::test]
+    async fn every_approver_is_asked_and_the_first_answer_wins() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let mut first = policy.connect();
+        let mut second = policy.connect();
+
+        let decision = tokio::spawn({
+            let policy = policy.clone();
+            async move { policy.decide(&call("shell_tool", json!({ "command": "ls" }))).await }
+        });
+        let from_first = first.requests.recv().await.unwrap();
+        let from_second = second.requests.recv().await.unwrap();
+        assert_eq!(from_first.request.id, from_second.request.id);
+
+        from_second.approve();
+        from_first.reject("too late");
+        assert!(matches!(decision.await.unwrap(), ToolDecision::Approve));
+    }
+
+    #[tokio::test]
+    async fn disconnected_approvers_are_skipped() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let first = policy.connect();
+        let mut second = policy.connect();
+        policy.disconnect(first.id);
+
+        let decision = tokio::spawn({
+            let policy = policy.clone();
+            async move { policy.decide(&call("shell_tool", json!({ "command": "ls" }))).await }
+        });
+        second.requests.recv().await.unwrap().reject("no");
+        assert!(is_denied(&decision.await.unwrap()));
+
+        policy.disconnect(second.id);
+        let shell = call("shell_tool", json!({ "command": "ls" }));
+        assert!(is_denied(&policy.decide(&shell).await));
+    }
+
+    #[tokio::test]
+    async fn dropped_requests_are_denied() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let mut approver = policy.connect();
+
+        let decision = tokio::spawn({
+            let policy = policy.clone();
+            async move { policy.decide(&call("shell_tool", json!({ "command": "ls" }))).await }
+        });
+        drop(approver.requests.recv().await.unwrap());
+        assert!(is_denied(&decision.await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn every_copy_settles_once_one_is_answered() {
+        let policy = ApprovalPolicy::sensitive_tools();
+        let mut first = policy.connect();
+        let mut second = policy.connect();
+
+        let decision = tokio::spawn({
+            let policy = policy.clone();
+            async move { policy.decide(&call("shell_tool", json!({ "command": "ls" }))).await }
+        });
+        let from_first = first.requests.recv().await.unwrap();
+        let from_second = second.requests.recv().await.unwrap();
+        let settled = from_first.settled();
+
+        from_second.approve();
+        assert!(matches!(decision.await.unwrap(), ToolDecision::Approve));
+        tokio::time::timeout(Duration::from_secs(1), settled).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_settle_when_they_time_out() {
+        let policy = ApprovalPolicy::sensitive_tools().with_timeout(Duration::from_millis(10));
+        let mut approver = policy.connect();
+
+        let decision = tokio::spawn({
+            let policy = policy.clone();
+            async move { policy.decide(&call("shell_tool", json!({ "command": "ls" }))).await }
+        });
+        let pending = approver.requests.recv().await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), pending.settled()).await.unwrap();
+        assert!(is_denied(&decision.await.unwrap()));
+    }
+}
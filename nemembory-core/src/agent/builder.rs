@@ -0,0 +1,339 @@
+use crate::agent::agent::NememboryAgent;
+use crate::agent::model::{
+    AgentOptions,
+    ModelError,
+    ModelProvider,
+    build_runnable_agent_with_options,
+    model_id,
+    render_preamble,
+    task_preamble,
+};
+use crate::agent::policy::ToolPolicy;
+use crate::tools::{
+    Credentials,
+    LinkToMarkdown,
+    NetworkPolicy,
+    OpenApiError,
+    OpenApiSpec,
+    RestApiTool,
+    Shell,
+    ShellSandbox,
+    ShellSessionTool,
+    ShellTool,
+    ToolRegistry,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configures a `NememboryAgent` before building it. Every option defaults to the
+/// behavior of `NememboryAgent::new`.
+pub struct NememboryAgentBuilder {
+    name: String,
+    provider: ModelProvider,
+    task: String,
+    preamble_template: Option<String>,
+    options: AgentOptions,
+    registry: ToolRegistry,
+    working_dir: Option<String>,
+    shell: Option<Shell>,
+    sandbox: Option<ShellSandbox>,
+    credentials: Option<Credentials>,
+    network: Option<NetworkPolicy>,
+    openapi_specs: Vec<OpenApiSpec>,
+}
+
+impl NememboryAgentBuilder {
+    pub fn new(name: &str, provider: ModelProvider) -> Self {
+        Self {
+            name: name.to_string(),
+            provider,
+            task: String::new(),
+            preamble_template: None,
+            options: AgentOptions::new(String::new()),
+            registry: ToolRegistry::with_builtin_tools(),
+            working_dir: None,
+            shell: None,
+            sandbox: None,
+            credentials: None,
+            network: None,
+            openapi_specs: Vec::new(),
+        }
+    }
+
+    /// Task inserted into the preamble
+    pub fn task(mut self, task: &str) -> Self {
+        self.task = task.to_string();
+        self
+    }
+
+    /// Model id overriding the provider default (e.g. `claude-opus-4-0`)
+    pub fn model(mut self, model: &str) -> Self {
+        self.options.model = Some(model.to_string());
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u64) -> Self {
+        self.options.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.options.temperature = Some(temperature);
+        self
+    }
+
+    /// Replaces the default preamble. `{task}` and `{date}` are substituted when building.
+    pub fn preamble_template(mut self, template: &str) -> Self {
+        self.preamble_template = Some(template.to_string());
+        self
+    }
+
+    /// Adds a document that is always part of the model context
+    pub fn context(mut self, document: &str) -> Self {
+        self.options.context.push(document.to_string());
+        self
+    }
+
+    /// Replaces the selected tools. Names must be registered in the builder's registry.
+    pub fn tools(mut self, tools: &[&str]) -> Self {
+        self.options.tools = tools
+            .iter()
+            .map(|tool| tool.to_string())
+            .collect();
+        self
+    }
+
+    /// Adds a single tool to the selection
+    pub fn tool(mut self, tool: &str) -> Self {
+        if !self.options.tools.iter().any(|t| t == tool) {
+            self.options.tools.push(tool.to_string());
+        }
+        self
+    }
+
+    /// Abandons tool calls that take longer than `timeout`
+    pub fn tool_timeout(mut self, timeout: Duration) -> Self {
+        self.options.tool_timeout = Some(timeout);
+        self
+    }
+
+    /// Adds a policy that can approve, deny, rewrite or abort every tool call
+    pub fn policy(mut self, policy: Arc<dyn ToolPolicy>) -> Self {
+        self.options.policies.add(policy);
+        self
+    }
+
+    /// Creates `working_dir` for the agent's logs; `shell_tool` and `shell_session` run
+    /// their commands there
+    pub fn working_dir(mut self, working_dir: &str) -> Self {
+        self.working_dir = Some(working_dir.to_string());
+        self
+    }
+
+    /// Shell used by `shell_tool` and `shell_session`, detected from the OS by default
+    pub fn shell(mut self, shell: Shell) -> Self {
+        self.shell = Some(shell);
+        self
+    }
+
+    /// Runs `shell_tool` and `shell_session` commands in `sandbox`, rooted at `working_dir`
+    /// unless the sandbox sets its own root
+    pub fn shell_sandbox(mut self, sandbox: ShellSandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Credential profiles `rest_api` can authenticate with; their secrets are redacted
+    /// from hook events
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Hosts and addresses `rest_api`, `link_to_markdown` and the OpenAPI tools may reach,
+    /// replacing the default policy that blocks localhost and private networks
+    pub fn network_policy(mut self, network: NetworkPolicy) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Selects one tool per operation of `spec`, registered when building, under the
+    /// builder's network policy when one is set
+    pub fn openapi(mut self, spec: &OpenApiSpec) -> Self {
+        for operation in &spec.operations {
+            self = self.tool(&operation.name);
+        }
+        self.openapi_specs.push(spec.clone());
+        self
+    }
+
+    pub fn registry(mut self, registry: ToolRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Registers the tools configured by the builder, replacing the default ones
+    fn register_tools(&mut self) -> Result<(), OpenApiError> {
+        if self.working_dir.is_some() || self.shell.is_some() || self.sandbox.is_some() {
+            let shell = self.shell.unwrap_or_default();
+            let working_dir = self.working_dir.clone();
+            let sandbox = self.sandbox.take();
+            let mut shell_tool = ShellTool::new().with_shell(shell);
+            if let Some(working_dir) = &working_dir {
+                shell_tool = shell_tool.with_working_dir(working_dir);
+            }
+            if let Some(sandbox) = &sandbox {
+                shell_tool = shell_tool.with_sandbox(sandbox.clone());
+            }
+            self.registry.register_tool(shell_tool);
+            // A new tool per agent, so agents built from this registry never share sessions
+            self.registry.register("shell_session", move || {
+                let mut session_tool = ShellSessionTool::new().with_shell(shell);
+                if let Some(working_dir) = &working_dir {
+                    session_tool = session_tool.with_working_dir(working_dir);
+                }
+                if let Some(sandbox) = &sandbox {
+                    session_tool = session_tool.with_sandbox(sandbox.clone());
+                }
+                Box::new(session_tool)
+            });
+        }
+        if self.credentials.is_some() || self.network.is_some() {
+            let mut rest_api = RestApiTool::new();
+            if let Some(credentials) = &self.credentials {
+                rest_api = rest_api.with_credentials(credentials.clone());
+            }
+            if let Some(network) = &self.network {
+                rest_api = rest_api.with_network_policy(network.clone());
+                let link_to_markdown = LinkToMarkdown::new().with_network_policy(network.clone());
+                self.registry.register_tool(link_to_markdown);
+            }
+            self.registry.register_tool(rest_api);
+        }
+        for spec in self.openapi_specs.drain(..) {
+            let spec = match &self.network {
+                Some(network) => spec.with_network_policy(network.clone()),
+                None => spec,
+            };
+            self.registry.register_openapi(&spec)?;
+        }
+        Ok(())
+    }
+
+    /// Fails when a selected tool is not registered, an OpenAPI spec cannot be registered or
+    /// the model provider's client cannot be created, e.g. without its api key
+    pub fn build(mut self) -> Result<NememboryAgent, ModelError> {
+        self.options.preamble = match &self.preamble_template {
+            Some(template) => render_preamble(template, &self.task),
+            None => {
+                let markdown_output = matches!(self.provider, ModelProvider::OpenRouter(_));
+                task_preamble(&self.task, markdown_output)
+            }
+        };
+        self.register_tools()?;
+        let model = model_id(&self.provider, &self.options);
+        let agent = build_runnable_agent_with_options(
+            self.provider,
+            &self.options,
+            &self.registry
+        )?;
+        let mut agent = NememboryAgent::from_runnable(&self.name, agent);
+        agent.model = model;
+        agent.tool_policies = self.options.policies;
+        if let Some(credentials) = self.credentials {
+            agent.credentials = credentials;
+        }
+        Ok(match &self.working_dir {
+            Some(working_dir) => agent.create_working_directory(working_dir),
+            None => agent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::mock::MockScript;
+    use crate::agent::policy::{ ToolCall, ToolDecision };
+
+    const SPEC: &str =
+        r#"{
+        "openapi": "3.0.0",
+        "servers": [{ "url": "https://pets.example.com" }],
+        "paths": { "/pets": { "get": { "operationId": "listPets" } } }
+    }"#;
+
+    fn builder() -> NememboryAgentBuilder {
+        NememboryAgentBuilder::new("builder_agent", ModelProvider::Mock(MockScript::default()))
+    }
+
+    #[test]
+    fn builds_with_the_model_and_policies() {
+        let agent = builder()
+            .model("mock-large")
+            .policy(Arc::new(|_: &ToolCall| ToolDecision::Approve))
+            .build()
+            .unwrap();
+        assert_eq!(agent.name, "builder_agent");
+        assert_eq!(agent.model, "mock-large");
+        assert!(!agent.tool_policies.is_empty());
+        assert_eq!(builder().build().unwrap().model, "mock");
+    }
+
+    #[test]
+    fn tools_are_selected_once_and_must_be_registered() {
+        let builder = builder().tools(&["get_date"]).tool("web_search").tool("get_date");
+        assert_eq!(builder.options.tools, ["get_date", "web_search"]);
+
+        let Err(ModelError::UnknownTools(error)) = builder.tool("web_serach").build() else {
+            panic!("expected an unknown tools error");
+        };
+        assert_eq!(error.0, ["web_serach"]);
+    }
+
+    #[test]
+    fn openapi_operations_are_selected_and_registered() {
+        let spec = OpenApiSpec::from_json(SPEC).unwrap();
+        let mut builder = builder().tools(&[]).openapi(&spec);
+        assert_eq!(builder.options.tools, ["listPets"]);
+
+        builder.register_tools().unwrap();
+        assert_eq!(builder.registry.http_method("listPets"), Some("GET"));
+    }
+
+    #[test]
+    fn openapi_operations_named_like_builtin_tools_are_rejected() {
+        let spec = OpenApiSpec::from_json(&SPEC.replace("listPets", "web_search")).unwrap();
+        let result = builder().openapi(&spec).build();
+        let Err(ModelError::OpenApi(OpenApiError::NameClash(name))) = result else {
+            panic!("expected a name clash");
+        };
+        assert_eq!(name, "web_search");
+    }
+
+    #[test]
+    fn working_dir_is_created() {
+        let dir = std::env::temp_dir().join(format!("nemembory-builder-{}", std::process::id()));
+        let agent = builder().working_dir(dir.to_str().unwrap()).build().unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(agent.working_dir.as_deref(), dir.to_str());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn every_agent_gets_its_own_shell_sessions() {
+        let dir = std::env::temp_dir();
+        let mut builder = builder().working_dir(dir.to_str().unwrap());
+        builder.register_tools().unwrap();
+        let names = ["shell_session".to_string()];
+        let first = builder.registry.build(&names).unwrap().remove(0);
+        let second = builder.registry.build(&names).unwrap().remove(0);
+
+        let started = first.call(r#"{"action":"start"}"#.to_string()).await.unwrap();
+        assert!(started.contains("session-1"), "{}", started);
+        let exec = r#"{"action":"exec","session_id":"session-1","command":"echo hi"}"#;
+        assert!(second.call(exec.to_string()).await.is_err());
+        assert!(first.call(exec.to_string()).await.unwrap().contains("hi"));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::agent::agent::{ Message, MessageRole };
+
+/// Prompt used to fold the oldest messages into a single summary message
+pub const SUMMARY_PROMPT: &str =
+    "Summarize the conversation so far in a few short paragraphs. Keep every fact, decision, \
+    open question and tool result that later turns may depend on. Reply with the summary only.";
+
+/// Controls when and how `NememboryAgent` compacts its history
+#[derive(Debug, Clone)]
+pub struct CompactionConfig {
+    /// Estimated token count above which the history is compacted
+    pub token_budget: usize,
+    /// Number of most recent messages that are never summarized
+    pub keep_recent: usize,
+}
+
+impl CompactionConfig {
+    pub fn new(token_budget: usize) -> Self {
+        Self { token_budget, keep_recent: 4 }
+    }
+
+    pub fn keep_recent(mut self, keep_recent: usize) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Returns how many of the oldest messages should be summarized, if any
+    pub fn split_point(&self, messages: &[Message]) -> Option<usize> {
+        if estimate_tokens(messages) <= self.token_budget {
+            return None;
+        }
+        let split = messages.len().saturating_sub(self.keep_recent);
+        // Folding a single message into a summary does not save anything
+        if split < 2 {
+            return None;
+        }
+        Some(split)
+    }
+}
+
+/// Restores compacted history from a stored conversation. The summary is stored with the
+/// timestamp of the first message it kept, so the last summary replaces every earlier message.
+pub fn apply_compaction(messages: Vec<Message>) -> Vec<Message> {
+    let Some(position) = messages
+        .iter()
+        .rposition(|m| matches!(m.role, MessageRole::Summary)) else {
+        return messages;
+    };
+    let summary = messages[position].clone();
+    let since = summary.time_stamp;
+    let kept = messages
+        .into_iter()
+        .enumerate()
+        .filter(|(index, m)| *index != position && m.time_stamp >= since)
+        .map(|(_, m)| m);
+    std::iter::once(summary).chain(kept).collect()
+}
+
+/// Rough token estimate (~4 characters per token), good enough to enforce a budget
+pub fn estimate_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|m| m.message.chars().count() / 4 + 4)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{ Duration, Utc };
+
+    fn history(count: usize) -> Vec<Message> {
+        let start = Utc::now();
+        (0..count)
+            .map(|i| {
+                let role = if i % 2 == 0 { MessageRole::User } else { MessageRole::Assistant };
+                let mut message = Message::new(role, format!("message {}", i));
+                message.time_stamp = start + Duration::seconds(i as i64);
+                message
+            })
+            .collect()
+    }
+
+    #[test]
+    fn history_under_budget_is_not_split() {
+        let messages = history(6);
+        let config = CompactionConfig::new(estimate_tokens(&messages));
+        assert_eq!(config.split_point(&messages), None);
+    }
+
+    #[test]
+    fn split_keeps_the_recent_messages() {
+        let messages = history(10);
+        let config = CompactionConfig::new(10).keep_recent(4);
+        assert_eq!(config.split_point(&messages), Some(6));
+    }
+
+    #[test]
+    fn single_message_is_not_summarized() {
+        let messages = history(5);
+        let config = CompactionConfig::new(10).keep_recent(4);
+        assert_eq!(config.split_point(&messages), None);
+    }
+
+    #[test]
+    fn stored_summary_replaces_earlier_messages() {
+        let mut stored = history(6);
+        let mut summary = Message::new(MessageRole::Summary, "summary".to_string());
+        summary.time_stamp = stored[4].time_stamp;
+        stored.insert(5, summary);
+
+        let restored = apply_compaction(stored);
+        let texts: Vec<&str> = restored
+            .iter()
+            .map(|m| m.message.as_str())
+            .collect();
+        assert_eq!(texts, ["summary", "message 4", "message 5"]);
+    }
+
+    #[test]
+    fn history_without_summary_is_unchanged() {
+        assert_eq!(apply_compaction(history(3)).len(), 3);
+    }
+}
@@ -0,0 +1,47 @@
+use futures::Stream;
+use serde::{ Deserialize, Serialize };
+use std::pin::Pin;
+
+/// Everything that happens during a streamed run, in the order it happens
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// A chunk of assistant text
+    TextDelta {
+        text: String,
+    },
+    /// The model requested a tool call
+    ToolCallStarted {
+        id: String,
+        name: String,
+        args: String,
+    },
+    /// A chunk of tool call arguments, sent while the model is still writing them
+    ToolCallArgsDelta {
+        id: String,
+        delta: String,
+    },
+    /// The tool finished and its result is sent back to the model
+    ToolResult {
+        id: String,
+        result: String,
+    },
+    Reasoning {
+        text: String,
+    },
+    /// Token usage of the whole run
+    Usage {
+        input_tokens: u64,
+        output_tokens: u64,
+    },
+    /// The final response; always the last event of a successful run
+    Final {
+        response: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// A pinned, boxed stream of agent events
+pub type EventStream<'a> = Pin<Box<dyn Stream<Item = AgentEvent> + Send + 'a>>;
@@ -1,47 +1,56 @@
-use rig::{ agent::{ CancelSignal, PromptHook }, completion::CompletionModel };
+use rig::{
+    agent::{ CancelSignal, PromptHook, StreamingPromptHook },
+    completion::{ AssistantContent, CompletionModel, GetTokenUsage },
+};
 use thiserror::Error;
-use std::sync::Arc;
-use std::future::Future;
-use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use tokio_util::sync::CancellationToken;
 
-use crate::agent::agent::Message;
+use crate::agent::agent::MessageRole;
+use crate::agent::observer::{
+    AgentObserver,
+    CompletionEvent,
+    ToolCallEvent,
+    ToolResultEvent,
+    parse_args,
+};
+use crate::agent::policy::{ ToolDecision, ToolPolicies };
+use crate::agent::usage::{ TokenUsage, UsageTracker };
+use crate::tools::Credentials;
 
 #[derive(Debug, Error)]
 pub enum AgentHookError {
     #[error("Agent Hook on tool call error: {0}")] AgentHookError(String),
 }
 
-pub type LlmResponseFunctionType = Vec<Arc<dyn Fn(HashMap<String, String>) + Send + Sync>>;
-
+/// Bridges rig's hook callbacks to the registered `AgentObserver`s as typed events
 #[derive(Clone)]
 pub struct LlmResponseHooks {
-    pub(crate) on_tool_call_callback: LlmResponseFunctionType,
-    pub(crate) on_completion_response_callback: LlmResponseFunctionType,
-    pub(crate) on_tool_call_result_callback: LlmResponseFunctionType,
+    pub(crate) observers: Vec<Arc<dyn AgentObserver>>,
+    pub(crate) agent_name: String,
+    /// Model turns started in the current run, shared by the clones handed to rig
+    pub(crate) turns: Arc<AtomicUsize>,
+    pub(crate) usage_tracker: Option<UsageTracker>,
+    /// Usage recorded in the current run, shared by the clones handed to rig
+    recorded_usage: Arc<Mutex<TokenUsage>>,
+    /// Text streamed in the current turn, reported with its completion event
+    turn_text: Arc<Mutex<String>>,
+    pub(crate) cancellation: Option<CancellationToken>,
+    pub(crate) policies: ToolPolicies,
+    /// Secrets replaced in every event before observers see it
+    pub(crate) credentials: Credentials,
+    /// Arguments rewritten by a policy, as (tool name, model args, rewritten args), until
+    /// the tool returns
+    rewrites: Arc<Mutex<Vec<(String, String, String)>>>,
 }
 
 impl<M: CompletionModel> PromptHook<M> for LlmResponseHooks {
-    async fn on_tool_call(&self, tool_name: &str, args: &str, _cancel_sig: CancelSignal) {
-        let callbacks = self.on_tool_call_callback.clone();
-        let tool_name = tool_name.to_string();
-        let args = args.to_string();
-        let handles: Vec<_> = callbacks
-            .into_iter()
-            .map(|callback| {
-                let tool_name = tool_name.clone();
-                let args = args.clone();
-                tokio::spawn(async move {
-                    let mut params = HashMap::new();
-                    params.insert("tool_name".to_string(), tool_name);
-                    params.insert("args".to_string(), args);
-                    callback(params);
-                })
-            })
-            .collect();
-
-        for handle in handles {
-            let _ = handle.await;
-        }
+    async fn on_tool_call(&self, tool_name: &str, args: &str, cancel_sig: CancelSignal) {
+        self.check_cancelled(&cancel_sig);
+        let args = self.decide_tool_call(tool_name, args).await;
+        self.check_cancelled(&cancel_sig);
+        self.notify_tool_call(tool_name, &args).await;
     }
 
     async fn on_tool_result(
@@ -49,108 +58,263 @@ impl<M: CompletionModel> PromptHook<M> for LlmResponseHooks {
         tool_name: &str,
         args: &str,
         result: &str,
-        _cancel_sig: CancelSignal
+        cancel_sig: CancelSignal
     ) {
-        let callbacks = self.on_tool_call_result_callback.clone();
-        let tool_name = tool_name.to_string();
-        let args = args.to_string();
-        let result = result.to_string();
-        let handles: Vec<_> = callbacks
-            .into_iter()
-            .map(|callback| {
-                let tool_name = tool_name.clone();
-                let args = args.clone();
-                let result = result.clone();
-                tokio::spawn(async move {
-                    let mut params = HashMap::new();
-                    params.insert("tool_name".to_string(), tool_name);
-                    params.insert("args".to_string(), args);
-                    params.insert("result".to_string(), result);
-                    callback(params);
-                })
-            })
-            .collect();
-
-        for handle in handles {
-            let _ = handle.await;
-        }
+        self.check_cancelled(&cancel_sig);
+        let args = self.effective_args(tool_name, args);
+        self.notify_tool_result(tool_name, &args, result).await;
     }
 
-    fn on_completion_call(
+    async fn on_completion_call(
         &self,
-        prompt: &rig::message::Message,
-        history: &[rig::message::Message],
-        _cancel_sig: CancelSignal
-    ) -> impl Future<Output = ()> + Send {
-        async move {
-            dbg!(
-                "on_completion_call called with prompt: {:?}, history length: {:?}",
-                prompt,
-                &history
-            );
-        }
+        _prompt: &rig::message::Message,
+        _history: &[rig::message::Message],
+        cancel_sig: CancelSignal
+    ) {
+        self.check_cancelled(&cancel_sig);
+        self.start_turn();
     }
 
     async fn on_completion_response(
         &self,
-        prompt: &rig::message::Message,
+        _prompt: &rig::message::Message,
         response: &rig::completion::CompletionResponse<<M as CompletionModel>::Response>,
         cancel_sig: CancelSignal
     ) {
-        dbg!("on_completion_response called with prompt");
-        let callbacks = self.on_completion_response_callback.clone();
-        let _response = &response;
-
-        let content: Message = prompt.clone().into();
-        let handles: Vec<_> = callbacks
-            .into_iter()
-            .map(|callback| {
-                let content = content.clone();
-                tokio::spawn(async move {
-                    let mut params = HashMap::new();
-                    params.insert("content".to_string(), content.message);
-                    params.insert("role".to_string(), format!("{:?}", content.role));
-                    callback(params);
-                })
+        self.check_cancelled(&cancel_sig);
+        let content = response.choice
+            .iter()
+            .filter_map(|content| {
+                match content {
+                    AssistantContent::Text(text) => Some(text.text.clone()),
+                    _ => None,
+                }
             })
-            .collect();
+            .collect::<String>();
+        self.record_usage(response.usage.into());
+        self.notify_completion(&content, response.usage.into()).await;
+    }
+}
 
-        for handle in handles {
-            let _ = handle.await;
-        }
+/// For streaming requests made with rig directly. `NememboryAgent` makes the same calls from
+/// the stream items, as rig panics on hooked streams without history.
+impl<M: CompletionModel> StreamingPromptHook<M> for LlmResponseHooks {
+    async fn on_completion_call(
+        &self,
+        _prompt: &rig::message::Message,
+        _history: &[rig::message::Message],
+        cancel_sig: CancelSignal
+    ) {
+        self.check_cancelled(&cancel_sig);
+        self.start_turn();
+    }
+
+    async fn on_text_delta(
+        &self,
+        _text_delta: &str,
+        aggregated_text: &str,
+        cancel_sig: CancelSignal
+    ) {
+        self.check_cancelled(&cancel_sig);
+        *self.turn_text.lock().unwrap() = aggregated_text.to_string();
+    }
+
+    async fn on_stream_completion_response_finish(
+        &self,
+        _prompt: &rig::message::Message,
+        response: &<M as CompletionModel>::StreamingResponse,
+        cancel_sig: CancelSignal
+    ) {
+        self.check_cancelled(&cancel_sig);
+        let usage = response.token_usage().map(TokenUsage::from).unwrap_or_default();
+        let content = std::mem::take(&mut *self.turn_text.lock().unwrap());
+        self.finish_streamed_turn(&content, usage).await;
+    }
+
+    async fn on_tool_call(&self, tool_name: &str, args: &str, cancel_sig: CancelSignal) {
+        self.check_cancelled(&cancel_sig);
+        let args = self.decide_tool_call(tool_name, args).await;
+        self.check_cancelled(&cancel_sig);
+        self.notify_tool_call(tool_name, &args).await;
+    }
+
+    async fn on_tool_result(
+        &self,
+        tool_name: &str,
+        args: &str,
+        result: &str,
+        cancel_sig: CancelSignal
+    ) {
+        self.check_cancelled(&cancel_sig);
+        let args = self.effective_args(tool_name, args);
+        self.notify_tool_result(tool_name, &args, result).await;
+    }
+}
+
+impl Default for LlmResponseHooks {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl LlmResponseHooks {
     pub fn new() -> Self {
         Self {
-            on_tool_call_callback: Vec::new(),
-            on_completion_response_callback: Vec::new(),
-            on_tool_call_result_callback: Vec::new(),
+            observers: Vec::new(),
+            agent_name: String::new(),
+            turns: Arc::new(AtomicUsize::new(0)),
+            usage_tracker: None,
+            recorded_usage: Arc::new(Mutex::new(TokenUsage::default())),
+            turn_text: Arc::new(Mutex::new(String::new())),
+            cancellation: None,
+            policies: ToolPolicies::new(),
+            credentials: Credentials::new(),
+            rewrites: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn add_observer(&mut self, observer: Arc<dyn AgentObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Hooks for a new run of `agent_name`, with the turn count starting over
+    pub(crate) fn for_run(&self, agent_name: &str) -> Self {
+        Self {
+            agent_name: agent_name.to_string(),
+            turns: Arc::new(AtomicUsize::new(0)),
+            recorded_usage: Arc::new(Mutex::new(TokenUsage::default())),
+            turn_text: Arc::new(Mutex::new(String::new())),
+            rewrites: Arc::new(Mutex::new(Vec::new())),
+            ..self.clone()
+        }
+    }
+
+    /// Records the token usage of every completion response in `tracker`
+    pub fn set_usage_tracker(&mut self, tracker: UsageTracker) {
+        self.usage_tracker = Some(tracker);
+    }
+
+    /// Stops the multi-turn loop at the next hook call once `token` is cancelled
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Cancels the run once a policy aborted one of its tool calls
+    pub fn set_policies(&mut self, policies: ToolPolicies) {
+        self.policies = policies;
+    }
+
+    /// Redacts the secrets of `credentials` from tool arguments, results and completions
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = credentials;
+    }
+
+    fn check_cancelled(&self, cancel_sig: &CancelSignal) {
+        let cancelled = self.cancellation.as_ref().is_some_and(|token| token.is_cancelled());
+        if cancelled || self.policies.aborted().is_some() {
+            cancel_sig.cancel();
         }
     }
 
-    pub fn add_completion_response_callback<F>(&mut self, callback: F)
-        where F: Fn(HashMap<String, String>) + Send + Sync + 'static
-    {
-        self.on_completion_response_callback.push(Arc::new(callback));
+    pub(crate) fn record_usage(&self, usage: TokenUsage) {
+        *self.recorded_usage.lock().unwrap() += usage;
+        if let Some(tracker) = &self.usage_tracker {
+            tracker.record_turn(usage);
+        }
+    }
+
+    /// Records the part of a run's `total` usage that no turn recorded. Streamed turns that
+    /// only call tools report their usage in rig's run total alone.
+    pub(crate) fn record_remaining_usage(&self, total: TokenUsage) {
+        let recorded = *self.recorded_usage.lock().unwrap();
+        let remaining = TokenUsage::new(
+            total.input_tokens.saturating_sub(recorded.input_tokens),
+            total.output_tokens.saturating_sub(recorded.output_tokens)
+        );
+        if remaining.total_tokens() > 0 {
+            self.record_usage(remaining);
+        }
+    }
+
+    /// Marks the start of a model turn, also used by agents that do not go through rig
+    pub(crate) fn start_turn(&self) {
+        self.turns.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn current_turn(&self) -> usize {
+        self.turns.load(Ordering::SeqCst).saturating_sub(1)
     }
 
-    pub fn add_tool_call_callback<F>(&mut self, callback: F)
-        where F: Fn(HashMap<String, String>) + Send + Sync + 'static
-    {
-        self.on_tool_call_callback.push(Arc::new(callback));
+    /// Lets the policies decide as soon as the model calls a tool and returns the arguments
+    /// the tool runs with; `PolicyTool` applies the same decision
+    pub(crate) async fn decide_tool_call(&self, tool_name: &str, args: &str) -> String {
+        if self.policies.is_empty() {
+            return args.to_string();
+        }
+        match self.policies.decide_ahead(tool_name, args).await {
+            ToolDecision::Rewrite(rewritten) => {
+                let rewritten = rewritten.to_string();
+                let entry = (tool_name.to_string(), args.to_string(), rewritten.clone());
+                self.rewrites.lock().unwrap().push(entry);
+                rewritten
+            }
+            _ => args.to_string(),
+        }
+    }
+
+    /// The arguments a returning tool ran with, given the ones the model sent
+    pub(crate) fn effective_args(&self, tool_name: &str, args: &str) -> String {
+        let mut rewrites = self.rewrites.lock().unwrap();
+        match rewrites.iter().position(|(name, raw, _)| name == tool_name && raw == args) {
+            Some(index) => rewrites.remove(index).2,
+            None => args.to_string(),
+        }
+    }
+
+    pub(crate) async fn notify_tool_call(&self, tool_name: &str, args: &str) {
+        let event = ToolCallEvent {
+            agent_name: self.agent_name.clone(),
+            turn: self.current_turn(),
+            tool_name: tool_name.to_string(),
+            args: self.credentials.redact_value(&parse_args(args)),
+            time_stamp: chrono::Utc::now(),
+        };
+        for observer in &self.observers {
+            observer.on_tool_call(&event).await;
+        }
+    }
+
+    pub(crate) async fn notify_tool_result(&self, tool_name: &str, args: &str, result: &str) {
+        let event = ToolResultEvent {
+            agent_name: self.agent_name.clone(),
+            turn: self.current_turn(),
+            tool_name: tool_name.to_string(),
+            args: self.credentials.redact_value(&parse_args(args)),
+            result: self.credentials.redact(result),
+            time_stamp: chrono::Utc::now(),
+        };
+        for observer in &self.observers {
+            observer.on_tool_result(&event).await;
+        }
     }
 
-    pub fn add_tool_call_result_callback<F>(&mut self, callback: F)
-        where F: Fn(HashMap<String, String>) + Send + Sync + 'static
-    {
-        self.on_tool_call_result_callback.push(Arc::new(callback));
+    /// Records a streamed turn and reports its text once the model finished it
+    pub(crate) async fn finish_streamed_turn(&self, content: &str, usage: TokenUsage) {
+        self.record_usage(usage);
+        self.notify_completion(content, usage).await;
     }
 
-    pub fn call_callbacks(&self, params: HashMap<String, String>) {
-        for callback in &self.on_tool_call_callback {
-            callback(params.clone());
+    pub(crate) async fn notify_completion(&self, content: &str, usage: TokenUsage) {
+        let event = CompletionEvent {
+            agent_name: self.agent_name.clone(),
+            turn: self.current_turn(),
+            role: MessageRole::Assistant,
+            content: self.credentials.redact(content),
+            usage,
+            time_stamp: chrono::Utc::now(),
+        };
+        for observer in &self.observers {
+            observer.on_completion(&event).await;
         }
     }
 }
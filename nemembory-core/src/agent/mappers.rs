@@ -6,6 +6,10 @@ impl From<Message> for rig::message::Message {
             crate::agent::agent::MessageRole::User => rig::message::Message::user(message.message),
             crate::agent::agent::MessageRole::Assistant =>
                 rig::message::Message::assistant(message.message),
+            crate::agent::agent::MessageRole::Summary =>
+                rig::message::Message::user(
+                    format!("Summary of the earlier conversation:\n{}", message.message)
+                ),
         }
     }
 }
@@ -0,0 +1,250 @@
+use async_trait::async_trait;
+use rig::completion::{ CompletionError, PromptError };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+
+use crate::agent::agent::RunnableAgent;
+use crate::agent::compaction::SUMMARY_PROMPT;
+use crate::agent::events::{ AgentEvent, EventStream };
+use crate::agent::hooks::LlmResponseHooks;
+use crate::agent::policy::{ PolicyError, ToolDecision };
+use crate::agent::usage::TokenUsage;
+
+/// A tool call the mock pretends the model made, with the result the tool "returned"
+#[derive(Debug, Clone)]
+pub struct MockToolCall {
+    pub name: String,
+    pub args: String,
+    pub result: String,
+    /// How long the tool "runs" before its result is reported
+    pub delay: Duration,
+}
+
+impl MockToolCall {
+    pub fn new(name: &str, args: &str, result: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            args: args.to_string(),
+            result: result.to_string(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Makes the tool take `delay` to return, e.g. to exercise cancellation and timeouts
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// One scripted answer: the tool calls made before answering, the final text and
+/// the chunks yielded when streaming
+#[derive(Debug, Clone, Default)]
+pub struct MockResponse {
+    pub text: String,
+    pub tool_calls: Vec<MockToolCall>,
+    /// Stream chunks; when empty the whole text is streamed as a single chunk
+    pub chunks: Vec<String>,
+    /// Token usage reported for the run
+    pub usage: TokenUsage,
+}
+
+impl MockResponse {
+    pub fn text(text: &str) -> Self {
+        Self { text: text.to_string(), ..Default::default() }
+    }
+
+    pub fn with_tool_call(mut self, tool_call: MockToolCall) -> Self {
+        self.tool_calls.push(tool_call);
+        self
+    }
+
+    pub fn with_chunks(mut self, chunks: &[&str]) -> Self {
+        self.chunks = chunks
+            .iter()
+            .map(|chunk| chunk.to_string())
+            .collect();
+        self
+    }
+
+    pub fn with_usage(mut self, input_tokens: u64, output_tokens: u64) -> Self {
+        self.usage = TokenUsage::new(input_tokens, output_tokens);
+        self
+    }
+
+    fn stream_chunks(&self) -> Vec<String> {
+        if self.chunks.is_empty() { vec![self.text.clone()] } else { self.chunks.clone() }
+    }
+}
+
+/// Responses returned in order by `MockRunnableAgent`. The last response repeats once
+/// the script is exhausted; an empty script echoes the prompt back.
+#[derive(Debug, Clone, Default)]
+pub struct MockScript {
+    pub responses: Vec<MockResponse>,
+}
+
+impl MockScript {
+    pub fn new(responses: Vec<MockResponse>) -> Self {
+        Self { responses }
+    }
+}
+
+/// Deterministic `RunnableAgent` that needs no network access or api key. Hooks are
+/// called for every scripted tool call so handlers and callbacks can be exercised offline.
+pub struct MockRunnableAgent {
+    script: MockScript,
+    cursor: AtomicUsize,
+    prompts: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockRunnableAgent {
+    pub fn new(script: MockScript) -> Self {
+        Self {
+            script,
+            cursor: AtomicUsize::new(0),
+            prompts: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Shared log of every prompt received, readable after the mock is boxed into an agent
+    pub fn prompt_log(&self) -> Arc<Mutex<Vec<String>>> {
+        Arc::clone(&self.prompts)
+    }
+
+    fn next_response(&self, prompt: &str) -> MockResponse {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+
+        let index = self.cursor.fetch_add(1, Ordering::SeqCst);
+        match self.script.responses.len() {
+            0 => MockResponse::text(&format!("Mock response to: {}", prompt)),
+            len => self.script.responses[index.min(len - 1)].clone(),
+        }
+    }
+}
+
+/// Runs a scripted tool call past the agent's policies, as `PolicyTool` does for real tools.
+/// Returns the call with its effective args and result, or the abort message.
+async fn apply_policies(
+    hook: &LlmResponseHooks,
+    tool_call: &MockToolCall
+) -> Result<MockToolCall, String> {
+    let call = hook.policies.tool_call(&tool_call.name, &tool_call.args);
+    let mut tool_call = tool_call.clone();
+    match hook.policies.decide(&call).await {
+        ToolDecision::Approve => {}
+        ToolDecision::Rewrite(args) => {
+            tool_call.args = args.to_string();
+        }
+        ToolDecision::Deny(reason) => {
+            tool_call.result = PolicyError::Denied(reason).to_string();
+        }
+        ToolDecision::Abort(reason) => {
+            return Err(PolicyError::Aborted(reason).to_string());
+        }
+    }
+    Ok(tool_call)
+}
+
+#[async_trait]
+impl RunnableAgent for MockRunnableAgent {
+    async fn run(
+        &self,
+        prompt: &str,
+        _messages: &[rig::message::Message],
+        max_turns: usize,
+        nemembory_hook: &LlmResponseHooks
+    ) -> Result<String, PromptError> {
+        let response = self.next_response(prompt);
+
+        if response.tool_calls.len() > max_turns {
+            return Err(
+                PromptError::CompletionError(
+                    CompletionError::ProviderError(
+                        format!("Mock script needs more than {} turns", max_turns)
+                    )
+                )
+            );
+        }
+
+        nemembory_hook.start_turn();
+        for tool_call in &response.tool_calls {
+            let tool_call = apply_policies(nemembory_hook, tool_call).await.map_err(|message| {
+                PromptError::CompletionError(CompletionError::ProviderError(message))
+            })?;
+            nemembory_hook.notify_tool_call(&tool_call.name, &tool_call.args).await;
+            tokio::time::sleep(tool_call.delay).await;
+            nemembory_hook.notify_tool_result(
+                &tool_call.name,
+                &tool_call.args,
+                &tool_call.result
+            ).await;
+        }
+        nemembory_hook.record_usage(response.usage);
+        nemembory_hook.notify_completion(&response.text, response.usage).await;
+
+        Ok(response.text)
+    }
+
+    fn run_events(
+        &self,
+        prompt: &str,
+        _messages: &[rig::message::Message],
+        max_turns: usize,
+        nemembory_hook: &LlmResponseHooks
+    ) -> EventStream<'_> {
+        let response = self.next_response(prompt);
+        let hook = nemembory_hook.clone();
+
+        Box::pin(
+            async_stream::stream! {
+                if response.tool_calls.len() > max_turns {
+                    yield AgentEvent::Error {
+                        message: format!("Mock script needs more than {} turns", max_turns),
+                    };
+                    return;
+                }
+
+                hook.start_turn();
+                for (index, tool_call) in response.tool_calls.iter().enumerate() {
+                    let id = format!("mock-tool-call-{}", index);
+                    let tool_call = match apply_policies(&hook, tool_call).await {
+                        Ok(tool_call) => tool_call,
+                        Err(message) => {
+                            yield AgentEvent::Error { message };
+                            return;
+                        }
+                    };
+                    hook.notify_tool_call(&tool_call.name, &tool_call.args).await;
+                    yield AgentEvent::ToolCallStarted {
+                        id: id.clone(),
+                        name: tool_call.name.clone(),
+                        args: tool_call.args.clone(),
+                    };
+                    tokio::time::sleep(tool_call.delay).await;
+                    hook.notify_tool_result(&tool_call.name, &tool_call.args, &tool_call.result).await;
+                    yield AgentEvent::ToolResult { id, result: tool_call.result.clone() };
+                }
+                for chunk in response.stream_chunks() {
+                    yield AgentEvent::TextDelta { text: chunk };
+                }
+                hook.finish_streamed_turn(&response.text, response.usage).await;
+                yield AgentEvent::Usage {
+                    input_tokens: response.usage.input_tokens,
+                    output_tokens: response.usage.output_tokens,
+                };
+                yield AgentEvent::Final { response: response.text.clone() };
+            }
+        )
+    }
+
+    /// Answers with the next scripted text; its tool calls are not made
+    async fn summarize(
+        &self,
+        _messages: &[rig::message::Message]
+    ) -> Result<(String, TokenUsage), PromptError> {
+        let response = self.next_response(SUMMARY_PROMPT);
+        Ok((response.text, response.usage))
+    }
+}
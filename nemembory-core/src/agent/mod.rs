@@ -1,9 +1,43 @@
+#[allow(clippy::module_inception)]
 pub mod agent;
+pub mod approval;
+pub mod builder;
+pub mod compaction;
+pub mod events;
 pub mod hooks;
 pub mod mappers;
+pub mod mock;
 pub mod model;
-pub use agent::{ RunnableAgent, NememboryAgent };
-pub use model::{ ModelProvider, build_runnable_agent };
+pub mod observer;
+pub mod policy;
+pub mod recall;
+pub mod usage;
+pub use agent::{ RunAbort, RunnableAgent, NememboryAgent };
+pub use model::{
+    AgentOptions,
+    ModelError,
+    ModelProvider,
+    UnknownProviderError,
+    build_runnable_agent,
+    build_runnable_agent_with_options,
+    build_runnable_agent_with_preamble,
+};
+pub use approval::{ ApprovalPolicy, ApprovalRequest, Approver, PendingApproval };
+pub use builder::NememboryAgentBuilder;
+pub use events::{ AgentEvent, EventStream };
 pub use hooks::{ AgentHookError, LlmResponseHooks };
+pub use compaction::CompactionConfig;
+pub use policy::{
+    DenyCommands,
+    DenyHosts,
+    PolicyError,
+    ToolCall,
+    ToolDecision,
+    ToolPolicies,
+    ToolPolicy,
+};
+pub use observer::{ AgentObserver, CompletionEvent, ToolCallEvent, ToolResultEvent };
+pub use mock::{ MockResponse, MockRunnableAgent, MockScript, MockToolCall };
+pub use recall::{ Embedder, HashEmbedder, RigEmbedder, SemanticRecall };
+pub use usage::{ PriceTable, RunUsage, TokenUsage, UsageTracker };
 pub use crate::handlers::FileHandler;
-pub use mappers::*;
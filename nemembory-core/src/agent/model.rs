@@ -1,71 +1,358 @@
 use rig::{
-    client::{ CompletionClient, ProviderClient },
-    providers::{ anthropic, gemini, openrouter },
+    agent::AgentBuilder,
+    client::{ CompletionClient, Nothing, ProviderClient },
+    completion::{ CompletionModel, ToolDefinition },
+    providers::{ anthropic, gemini, ollama, openai, openrouter },
+    tool::{ Tool, ToolDyn, ToolError, server::ToolServer },
 };
 
 use chrono_tz::America::Toronto;
+use serde_json::value::RawValue;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::task::{ Context, Poll };
+use std::time::Duration;
 use crate::RunnableAgent;
-use crate::{ LinkToMarkdown, RestApiTool, ShellTool, WebSearch };
+use crate::tools::{ OpenApiError, PolicyTool, TimeoutTool, ToolRegistry, UnknownToolsError };
+use crate::agent::policy::ToolPolicies;
+use crate::agent::mock::{ MockRunnableAgent, MockScript };
+
+/// Tools attached to agents that are not built from a stored definition
+pub const DEFAULT_TOOLS: [&str; 3] = ["rest_api", "web_search", "shell_tool"];
+
+pub const DEFAULT_MAX_TOKENS: u64 = 1024;
+
+/// Base url used for Ollama when `OLLAMA_API_BASE_URL` is not set
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434";
+
+/// Base url used for `openai-compatible:<model>` when `OPENAI_COMPATIBLE_BASE_URL` is not set
+pub const DEFAULT_OPENAI_COMPATIBLE_BASE_URL: &str = "http://localhost:8000/v1";
+
+/// Environment variable holding the api key for `openai-compatible:<model>`
+pub const OPENAI_COMPATIBLE_API_KEY_ENV: &str = "OPENAI_COMPATIBLE_API_KEY";
 
 #[derive(Debug, Clone)]
 pub enum ModelProvider {
     Anthropic,
     Gemini,
     OpenRouter(String),
+    /// OpenAI model id, authenticated with `OPENAI_API_KEY`
+    OpenAi(String),
+    /// Local or remote Ollama server, no api key required
+    Ollama {
+        base_url: String,
+        model: String,
+    },
+    /// Any server speaking the OpenAI chat completions API (vLLM, LM Studio, internal gateways...)
+    OpenAiCompatible {
+        base_url: String,
+        /// Name of the environment variable holding the api key
+        api_key_env: String,
+        model: String,
+    },
+    /// Scripted offline agent for tests, see `MockRunnableAgent`
+    Mock(MockScript),
 }
 
-pub fn build_runnable_agent(provider: ModelProvider, task: String) -> Box<dyn RunnableAgent> {
-    let todays_date = chrono::Utc::now().with_timezone(&Toronto);
-    let preamble = format!(
-        r#"
-            # Goal:
-            You are an assistant here to help the user accomplish the following task: 
-            {} 
-            You have access to some tools that can help you with with performing your goal
-            Select the tool is most appropriate to perform the task specified by the user.
-            Follow these instructions closely.
-            1. Consider the user's request carefully and identify the core elements of the request.
-            2. Select which tool among those made available to you is appropriate given the context.
-            3. This is very important: never perform the operation yourself.
-            
-            # Context: 
-            Todays date is: {}"#,
-        &task,
-        todays_date
-    );
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown model provider: {0}")]
+pub struct UnknownProviderError(pub String);
+
+/// Why the runnable agent could not be built
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+    #[error("{0} is not set")] MissingApiKey(String),
+    #[error("Failed to build {provider} client: {message}")] Client {
+        provider: String,
+        message: String,
+    },
+    #[error(transparent)] UnknownTools(#[from] UnknownToolsError),
+    #[error(transparent)] OpenApi(#[from] OpenApiError),
+}
+
+impl FromStr for ModelProvider {
+    type Err = UnknownProviderError;
+
+    /// Parses `anthropic`, `gemini`, `mock`, `openrouter:<model>`, `openai:<model>`,
+    /// `ollama:<model>` and `openai-compatible:<model>`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (provider, model) = match value.split_once(':') {
+            Some((provider, model)) => (provider, Some(model.to_string())),
+            None => (value, None),
+        };
+        match (provider.to_lowercase().as_str(), model) {
+            ("anthropic", None) => Ok(ModelProvider::Anthropic),
+            ("gemini", None) => Ok(ModelProvider::Gemini),
+            ("mock", None) => Ok(ModelProvider::Mock(MockScript::default())),
+            ("openrouter", Some(model)) => Ok(ModelProvider::OpenRouter(model)),
+            ("openai", Some(model)) => Ok(ModelProvider::OpenAi(model)),
+            ("ollama", Some(model)) =>
+                Ok(ModelProvider::Ollama {
+                    base_url: std::env::var("OLLAMA_API_BASE_URL").unwrap_or_else(|_|
+                        DEFAULT_OLLAMA_BASE_URL.to_string()
+                    ),
+                    model,
+                }),
+            ("openai-compatible", Some(model)) =>
+                Ok(ModelProvider::OpenAiCompatible {
+                    base_url: std::env::var("OPENAI_COMPATIBLE_BASE_URL").unwrap_or_else(|_|
+                        DEFAULT_OPENAI_COMPATIBLE_BASE_URL.to_string()
+                    ),
+                    api_key_env: OPENAI_COMPATIBLE_API_KEY_ENV.to_string(),
+                    model,
+                }),
+            _ => Err(UnknownProviderError(value.to_string())),
+        }
+    }
+}
 
+pub fn build_runnable_agent(
+    provider: ModelProvider,
+    task: String
+) -> Result<Box<dyn RunnableAgent>, ModelError> {
+    let markdown_output = matches!(provider, ModelProvider::OpenRouter(_));
+    let options = AgentOptions::new(task_preamble(&task, markdown_output));
+    build_runnable_agent_with_options(provider, &options, &ToolRegistry::with_builtin_tools())
+}
+
+/// Builds an agent from a complete preamble, attaching only the named tools from `registry`
+pub fn build_runnable_agent_with_preamble(
+    provider: ModelProvider,
+    preamble: String,
+    tools: &[String],
+    registry: &ToolRegistry
+) -> Result<Box<dyn RunnableAgent>, ModelError> {
+    let mut options = AgentOptions::new(preamble);
+    options.tools = tools.to_vec();
+    build_runnable_agent_with_options(provider, &options, registry)
+}
+
+/// Settings applied to the underlying rig agent. Defaults match `build_runnable_agent`.
+#[derive(Debug, Clone)]
+pub struct AgentOptions {
+    /// Model id overriding the provider default
+    pub model: Option<String>,
+    pub max_tokens: u64,
+    pub temperature: Option<f64>,
+    pub preamble: String,
+    /// Static documents always added to the model context
+    pub context: Vec<String>,
+    pub tools: Vec<String>,
+    /// Maximum duration of a single tool call
+    pub tool_timeout: Option<Duration>,
+    /// Policies deciding whether each tool call may run
+    pub policies: ToolPolicies,
+}
+
+impl AgentOptions {
+    pub fn new(preamble: String) -> Self {
+        Self {
+            model: None,
+            max_tokens: DEFAULT_MAX_TOKENS,
+            temperature: None,
+            preamble,
+            context: Vec::new(),
+            tools: DEFAULT_TOOLS.map(String::from).to_vec(),
+            tool_timeout: None,
+            policies: ToolPolicies::new(),
+        }
+    }
+}
+
+/// The model id an agent built with `options` talks to, used for usage accounting
+pub fn model_id(provider: &ModelProvider, options: &AgentOptions) -> String {
+    if let Some(model) = &options.model {
+        return model.clone();
+    }
     match provider {
+        ModelProvider::Anthropic => anthropic::completion::CLAUDE_4_SONNET.to_string(),
+        ModelProvider::Gemini => gemini::completion::GEMINI_2_5_PRO_PREVIEW_06_05.to_string(),
+        ModelProvider::OpenRouter(model) | ModelProvider::OpenAi(model) => model.clone(),
+        ModelProvider::Ollama { model, .. } | ModelProvider::OpenAiCompatible { model, .. } =>
+            model.clone(),
+        ModelProvider::Mock(_) => "mock".to_string(),
+    }
+}
+
+/// Fails when a tool is not registered, the provider's api key is not set or its client cannot
+/// be created
+pub fn build_runnable_agent_with_options(
+    provider: ModelProvider,
+    options: &AgentOptions,
+    registry: &ToolRegistry
+) -> Result<Box<dyn RunnableAgent>, ModelError> {
+    let mut tools = registry.build(&options.tools)?;
+    if let Some(timeout) = options.tool_timeout {
+        tools = tools
+            .into_iter()
+            .map(|tool| Box::new(TimeoutTool::new(tool, timeout)) as Box<dyn ToolDyn>)
+            .collect();
+    }
+    if !options.policies.is_empty() {
+        tools = tools
+            .into_iter()
+            .map(|tool| {
+                if let Some(method) = registry.http_method(&tool.name()) {
+                    options.policies.set_http_method(&tool.name(), method);
+                }
+                Box::new(PolicyTool::new(tool, options.policies.clone())) as Box<dyn ToolDyn>
+            })
+            .collect();
+    }
+    let model = model_id(&provider, options);
+
+    // `from_env` panics without its key, so the key is checked first
+    let agent = match provider {
         ModelProvider::Anthropic => {
+            require_env("ANTHROPIC_API_KEY")?;
             let client: anthropic::Client = anthropic::Client::from_env();
-            let agent = client
-                .agent(anthropic::completion::CLAUDE_4_SONNET)
-                .preamble(&preamble)
-                .max_tokens(1024)
-                .tool(RestApiTool)
-                .tool(WebSearch)
-                .tool(ShellTool)
-                .build();
-            Box::new(agent)
+            finish_agent(client.agent(&model), options, tools)
         }
         ModelProvider::Gemini => {
+            require_env("GEMINI_API_KEY")?;
             let client: gemini::Client = gemini::Client::from_env();
-            let agent = client
-                .agent(gemini::completion::GEMINI_2_5_PRO_PREVIEW_06_05)
-                .preamble(&preamble)
-                .max_tokens(1024)
-                .tool(RestApiTool)
-                .tool(WebSearch)
-                .tool(ShellTool)
-                .build();
-            Box::new(agent)
+            finish_agent(client.agent(&model), options, tools)
+        }
+        ModelProvider::OpenRouter(_) => {
+            require_env("OPENROUTER_API_KEY")?;
+            let client = openrouter::Client::from_env();
+            finish_agent(client.agent(&model), options, tools)
         }
-        ModelProvider::OpenRouter(model) => open_router_agent(&model, task),
+        ModelProvider::OpenAi(_) => {
+            require_env("OPENAI_API_KEY")?;
+            let client = openai::Client::from_env();
+            finish_agent(client.agent(&model), options, tools)
+        }
+        ModelProvider::Ollama { base_url, .. } => {
+            let client: ollama::Client = ollama::Client::builder()
+                .api_key(Nothing)
+                .base_url(&base_url)
+                .build()
+                .map_err(|e| ModelError::Client {
+                    provider: "Ollama".to_string(),
+                    message: e.to_string(),
+                })?;
+            finish_agent(client.agent(&model), options, tools)
+        }
+        ModelProvider::OpenAiCompatible { base_url, api_key_env, .. } => {
+            let api_key = require_env(&api_key_env)?;
+            // Compatible servers implement chat completions, not the newer responses API
+            let client: openai::CompletionsClient = openai::CompletionsClient::builder()
+                .api_key(&api_key)
+                .base_url(&base_url)
+                .build()
+                .map_err(|e| ModelError::Client {
+                    provider: "OpenAI compatible".to_string(),
+                    message: e.to_string(),
+                })?;
+            finish_agent(client.agent(&model), options, tools)
+        }
+        ModelProvider::Mock(script) => Box::new(MockRunnableAgent::new(script)),
+    };
+    Ok(agent)
+}
+
+fn require_env(name: &str) -> Result<String, ModelError> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| ModelError::MissingApiKey(name.to_string()))
+}
+
+fn finish_agent<M: CompletionModel + 'static>(
+    builder: AgentBuilder<M>,
+    options: &AgentOptions,
+    tools: Vec<Box<dyn ToolDyn>>
+) -> Box<dyn RunnableAgent> {
+    let mut builder = builder.preamble(&options.preamble).max_tokens(options.max_tokens);
+    if let Some(temperature) = options.temperature {
+        builder = builder.temperature(temperature);
+    }
+    for document in &options.context {
+        builder = builder.context(document);
+    }
+    let server = tools
+        .into_iter()
+        .fold(ToolServer::new(), |server, tool| server.tool(DynTool(tool)));
+    Box::new(builder.tool_server_handle(server.run()).build())
+}
+
+/// Adapts a tool picked at runtime to rig's `Tool`, the only kind an `AgentBuilder` takes.
+/// Arguments and output are passed through as raw JSON.
+struct DynTool(Box<dyn ToolDyn>);
+
+impl Tool for DynTool {
+    const NAME: &'static str = "dyn_tool";
+    type Error = DynToolError;
+    type Args = Box<RawValue>;
+    type Output = Box<RawValue>;
+
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn definition(&self, prompt: String) -> impl Future<Output = ToolDefinition> + Send + Sync {
+        SyncFuture(Mutex::new(self.0.definition(prompt)))
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let output = self.0.call(args.get().to_string()).await?;
+        // Tools built on `Tool` answer JSON, the others may answer plain text
+        RawValue::from_string(output.clone())
+            .or_else(|_| serde_json::value::to_raw_value(&output))
+            .map_err(|e| ToolError::JsonError(e).into())
+    }
+}
+
+/// The error of the wrapped tool, which rig wraps in `ToolError` again
+#[derive(Debug)]
+struct DynToolError(Box<dyn std::error::Error + Send + Sync>);
+
+impl From<ToolError> for DynToolError {
+    fn from(error: ToolError) -> Self {
+        match error {
+            ToolError::ToolCallError(error) => Self(error),
+            error => Self(Box::new(error)),
+        }
+    }
+}
+
+impl std::fmt::Display for DynToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for DynToolError {}
+
+/// A `Send` future made `Sync`, as `Tool::definition` requires. It is only ever polled
+/// through `&mut`, so the mutex is never locked.
+struct SyncFuture<F>(Mutex<F>);
+
+impl<F: Future + Unpin> Future for SyncFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let future = self.get_mut().0.get_mut().unwrap_or_else(|e| e.into_inner());
+        Pin::new(future).poll(cx)
     }
 }
 
-fn open_router_agent(provider: &str, task: String) -> Box<dyn RunnableAgent> {
+pub(crate) fn task_preamble(task: &str, markdown_output: bool) -> String {
     let todays_date = chrono::Utc::now().with_timezone(&Toronto);
-    let preamble = format!(
+    let output = if markdown_output {
+        r#"
+            # Output:
+            Your output should always be in markdown format. Make sure that the information is readable 
+            and concise.
+            "#
+    } else {
+        ""
+    };
+
+    format!(
         r#"
             # Goal:
             You are an assistant here to help the user accomplish the following task: 
@@ -76,26 +363,91 @@ fn open_router_agent(provider: &str, task: String) -> Box<dyn RunnableAgent> {
             1. Consider the user's request carefully and identify the core elements of the request.
             2. Select which tool among those made available to you is appropriate given the context.
             3. This is very important: never perform the operation yourself.
-
-            # Output:
-            Your output should always be in markdown format. Make sure that the information is readable 
-            and concise.
-            
+            {}
             # Context: 
             Todays date is: {}"#,
-        &task,
+        task,
+        output,
         todays_date
-    );
-
-    let client = openrouter::Client::from_env();
-    let agent = client
-        .agent(provider)
-        .preamble(&preamble)
-        .max_tokens(1024)
-        .tool(RestApiTool)
-        .tool(WebSearch)
-        .tool(ShellTool)
-        .build();
-
-    Box::new(agent)
+    )
+}
+
+/// Fills the `{task}` and `{date}` placeholders of a custom preamble template
+pub fn render_preamble(template: &str, task: &str) -> String {
+    let todays_date = chrono::Utc::now().with_timezone(&Toronto);
+    template.replace("{task}", task).replace("{date}", &todays_date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::policy::{ ToolCall, ToolDecision };
+    use crate::tools::GetDate;
+    use std::sync::Arc;
+
+    #[test]
+    fn parses_providers() {
+        assert!(matches!("anthropic".parse(), Ok(ModelProvider::Anthropic)));
+        assert!(matches!("mock".parse(), Ok(ModelProvider::Mock(_))));
+        let provider: ModelProvider = "openai:gpt-4o".parse().unwrap();
+        assert!(matches!(provider, ModelProvider::OpenAi(model) if model == "gpt-4o"));
+        assert!("openai".parse::<ModelProvider>().is_err());
+        assert!("unknown:model".parse::<ModelProvider>().is_err());
+    }
+
+    #[test]
+    fn parses_openai_compatible() {
+        let provider: ModelProvider = "openai-compatible:qwen2.5".parse().unwrap();
+        let ModelProvider::OpenAiCompatible { api_key_env, model, .. } = provider else {
+            panic!("expected an OpenAI compatible provider");
+        };
+        assert_eq!(api_key_env, OPENAI_COMPATIBLE_API_KEY_ENV);
+        assert_eq!(model, "qwen2.5");
+    }
+
+    #[test]
+    fn missing_api_key_is_an_error() {
+        let provider = ModelProvider::OpenAiCompatible {
+            base_url: DEFAULT_OPENAI_COMPATIBLE_BASE_URL.to_string(),
+            api_key_env: "NEMEMBORY_TEST_MISSING_KEY".to_string(),
+            model: "model".to_string(),
+        };
+        let options = AgentOptions::new(String::new());
+        let registry = ToolRegistry::with_builtin_tools();
+        let result = build_runnable_agent_with_options(provider, &options, &registry);
+        let Err(ModelError::MissingApiKey(name)) = result else {
+            panic!("expected a missing api key error");
+        };
+        assert_eq!(name, "NEMEMBORY_TEST_MISSING_KEY");
+    }
+
+    #[test]
+    fn unknown_tools_are_an_error() {
+        let mut options = AgentOptions::new(String::new());
+        options.tools = vec!["web_search".to_string(), "web_serach".to_string()];
+        let registry = ToolRegistry::with_builtin_tools();
+        let result = build_runnable_agent_with_options(
+            ModelProvider::Mock(MockScript::default()),
+            &options,
+            &registry
+        );
+        let Err(ModelError::UnknownTools(error)) = result else {
+            panic!("expected an unknown tools error");
+        };
+        assert_eq!(error.0, ["web_serach"]);
+    }
+
+    #[tokio::test]
+    async fn dyn_tools_pass_arguments_output_and_errors_through() {
+        let tool = DynTool(Box::new(GetDate));
+        assert_eq!(ToolDyn::name(&tool), "get_date");
+        let output = ToolDyn::call(&tool, "null".to_string()).await.unwrap();
+        assert!(serde_json::from_str::<String>(&output).is_ok(), "{}", output);
+
+        let mut policies = ToolPolicies::new();
+        policies.add(Arc::new(|_: &ToolCall| ToolDecision::Deny("not today".to_string())));
+        let denied = DynTool(Box::new(PolicyTool::new(Box::new(GetDate), policies)));
+        let error = ToolDyn::call(&denied, "{}".to_string()).await.unwrap_err();
+        assert_eq!(error.to_string(), "ToolCallError: Tool call denied: not today");
+    }
 }
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use chrono::{ DateTime, Utc };
+use serde::{ Deserialize, Serialize };
+
+use crate::agent::agent::MessageRole;
+use crate::agent::usage::TokenUsage;
+
+/// Parses tool arguments, keeping them as a JSON string when they are not valid JSON
+pub fn parse_args(args: &str) -> serde_json::Value {
+    serde_json::from_str(args).unwrap_or_else(|_| serde_json::Value::String(args.to_string()))
+}
+
+/// The model asked to call a tool
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ToolCallEvent {
+    pub agent_name: String,
+    /// Index of the model turn within the run, starting at 0
+    pub turn: usize,
+    pub tool_name: String,
+    pub args: serde_json::Value,
+    pub time_stamp: DateTime<Utc>,
+}
+
+/// A tool call returned
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ToolResultEvent {
+    pub agent_name: String,
+    pub turn: usize,
+    pub tool_name: String,
+    pub args: serde_json::Value,
+    pub result: String,
+    pub time_stamp: DateTime<Utc>,
+}
+
+/// The model finished a turn
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CompletionEvent {
+    pub agent_name: String,
+    pub turn: usize,
+    pub role: MessageRole,
+    pub content: String,
+    pub usage: TokenUsage,
+    pub time_stamp: DateTime<Utc>,
+}
+
+/// Receives the events of every run. Methods are awaited in order, so observers can do
+/// async I/O; every method defaults to doing nothing.
+#[async_trait]
+pub trait AgentObserver: Send + Sync {
+    async fn on_tool_call(&self, _event: &ToolCallEvent) {}
+
+    async fn on_tool_result(&self, _event: &ToolResultEvent) {}
+
+    async fn on_completion(&self, _event: &CompletionEvent) {}
+}
@@ -0,0 +1,323 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{ Arc, Mutex };
+
+use crate::agent::observer::parse_args;
+use crate::tools::sandbox::denied_pattern;
+
+/// A tool call as seen by a policy, before the tool runs
+#[derive(Clone, Debug)]
+pub struct ToolCall {
+    pub tool_name: String,
+    pub args: serde_json::Value,
+    /// HTTP method of the request the tool sends, set for tools generated from OpenAPI
+    /// operations so policies can tell reads from writes
+    pub http_method: Option<String>,
+}
+
+/// What a policy wants to happen to a tool call
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToolDecision {
+    Approve,
+    /// Skip the tool; the reason is returned to the model as the tool error
+    Deny(String),
+    /// Run the tool with these arguments instead
+    Rewrite(serde_json::Value),
+    /// Skip the tool and stop the whole run
+    Abort(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("Tool call denied: {0}")] Denied(String),
+    #[error("Run aborted by policy: {0}")] Aborted(String),
+}
+
+/// Decides whether a tool call may run
+#[async_trait]
+pub trait ToolPolicy: Send + Sync {
+    async fn decide(&self, call: &ToolCall) -> ToolDecision;
+}
+
+#[async_trait]
+impl<F> ToolPolicy for F where F: Fn(&ToolCall) -> ToolDecision + Send + Sync {
+    async fn decide(&self, call: &ToolCall) -> ToolDecision {
+        self(call)
+    }
+}
+
+/// The policies of an agent, shared by its tools and its hooks. The hooks decide when the
+/// model calls a tool, so observers see the arguments the tool runs with, and the tool then
+/// applies that decision; an abort is remembered so the hooks can cancel the run through
+/// rig's `CancelSignal`.
+#[derive(Clone, Default)]
+pub struct ToolPolicies {
+    policies: Vec<Arc<dyn ToolPolicy>>,
+    aborted: Arc<Mutex<Option<String>>>,
+    /// Decisions made by the hooks and not yet applied, with the tool name and raw args
+    decided: Arc<Mutex<Vec<(String, String, ToolDecision)>>>,
+    /// HTTP methods of OpenAPI tools, see `ToolCall::http_method`
+    http_methods: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl std::fmt::Debug for ToolPolicies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolPolicies").field("policies", &self.policies.len()).finish()
+    }
+}
+
+impl ToolPolicies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, policy: Arc<dyn ToolPolicy>) {
+        self.policies.push(policy);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Reports `method` as the `http_method` of every call to `tool_name`
+    pub fn set_http_method(&self, tool_name: &str, method: &str) {
+        self.http_methods.lock().unwrap().insert(tool_name.to_string(), method.to_string());
+    }
+
+    /// The call a policy sees for raw `args`
+    pub fn tool_call(&self, tool_name: &str, args: &str) -> ToolCall {
+        ToolCall {
+            tool_name: tool_name.to_string(),
+            args: parse_args(args),
+            http_method: self.http_methods.lock().unwrap().get(tool_name).cloned(),
+        }
+    }
+
+    /// Decides a call ahead of the tool and keeps the decision for `decision`
+    pub(crate) async fn decide_ahead(&self, tool_name: &str, args: &str) -> ToolDecision {
+        let decision = self.decide(&self.tool_call(tool_name, args)).await;
+        let entry = (tool_name.to_string(), args.to_string(), decision.clone());
+        self.decided.lock().unwrap().push(entry);
+        decision
+    }
+
+    /// The decision made ahead for this call, or a new one when there is none
+    pub(crate) async fn decision(&self, tool_name: &str, args: &str) -> ToolDecision {
+        let decided = {
+            let mut decided = self.decided.lock().unwrap();
+            decided
+                .iter()
+                .position(|(name, raw, _)| name == tool_name && raw == args)
+                .map(|index| decided.remove(index).2)
+        };
+        match decided {
+            Some(decision) => decision,
+            None => self.decide(&self.tool_call(tool_name, args)).await,
+        }
+    }
+
+    /// Asks every policy in order. Rewritten arguments are passed on to the next policy;
+    /// the first deny or abort wins.
+    pub async fn decide(&self, call: &ToolCall) -> ToolDecision {
+        let mut call = call.clone();
+        let mut rewritten = false;
+        for policy in &self.policies {
+            match policy.decide(&call).await {
+                ToolDecision::Approve => {}
+                ToolDecision::Rewrite(args) => {
+                    call.args = args;
+                    rewritten = true;
+                }
+                ToolDecision::Abort(reason) => {
+                    *self.aborted.lock().unwrap() = Some(reason.clone());
+                    return ToolDecision::Abort(reason);
+                }
+                decision => {
+                    return decision;
+                }
+            }
+        }
+        if rewritten { ToolDecision::Rewrite(call.args) } else { ToolDecision::Approve }
+    }
+
+    /// Reason of the abort requested during the current run, if any
+    pub fn aborted(&self) -> Option<String> {
+        self.aborted.lock().unwrap().clone()
+    }
+
+    /// Clears the abort state and leftover decisions before a new run
+    pub fn reset(&self) {
+        *self.aborted.lock().unwrap() = None;
+        self.decided.lock().unwrap().clear();
+    }
+}
+
+/// Denies `shell_tool` and `shell_session` commands containing any of the given patterns,
+/// e.g. `rm -rf`, or running their program with the same flags in any order, through a path
+/// or with quotes (`/bin/rm -fr`, `r''m -rf`). Commands are read word by word, not parsed
+/// like a shell does, so this is a best-effort guard rather than a security boundary: pair
+/// it with a `ShellSandbox` allow list.
+pub struct DenyCommands {
+    patterns: Vec<String>,
+}
+
+impl DenyCommands {
+    pub fn new(patterns: &[&str]) -> Self {
+        Self { patterns: patterns.iter().map(|p| p.to_string()).collect() }
+    }
+}
+
+#[async_trait]
+impl ToolPolicy for DenyCommands {
+    async fn decide(&self, call: &ToolCall) -> ToolDecision {
+        if call.tool_name != "shell_tool" && call.tool_name != "shell_session" {
+            return ToolDecision::Approve;
+        }
+        let command = call.args["command"].as_str().unwrap_or_default();
+        match denied_pattern(command, &self.patterns) {
+            Some(pattern) =>
+                ToolDecision::Deny(format!("commands containing '{}' are not allowed", pattern)),
+            None => ToolDecision::Approve,
+        }
+    }
+}
+
+/// Denies `rest_api` and `link_to_markdown` calls to the given hosts. A pattern starting
+/// with `*.` matches every subdomain, e.g. `*.internal`.
+pub struct DenyHosts {
+    patterns: Vec<String>,
+}
+
+impl DenyHosts {
+    pub fn new(patterns: &[&str]) -> Self {
+        Self { patterns: patterns.iter().map(|p| p.to_lowercase()).collect() }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.patterns.iter().any(|pattern| host_matches(pattern, host))
+    }
+}
+
+/// Matches a lowercase `host` against `pattern`, where `*.example.com` matches every
+/// subdomain of example.com
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+        None => host == pattern,
+    }
+}
+
+#[async_trait]
+impl ToolPolicy for DenyHosts {
+    async fn decide(&self, call: &ToolCall) -> ToolDecision {
+        if call.tool_name != "rest_api" && call.tool_name != "link_to_markdown" {
+            return ToolDecision::Approve;
+        }
+        let Some(url) = call.args["url"].as_str() else {
+            return ToolDecision::Approve;
+        };
+        match reqwest::Url::parse(url) {
+            Ok(url) => {
+                let host = url.host_str().unwrap_or_default().to_lowercase();
+                if self.matches(&host) {
+                    ToolDecision::Deny(format!("requests to {} are not allowed", host))
+                } else {
+                    ToolDecision::Approve
+                }
+            }
+            Err(e) => ToolDecision::Deny(format!("invalid url {}: {}", url, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(tool_name: &str, args: serde_json::Value) -> ToolCall {
+        ToolCall { tool_name: tool_name.to_string(), args, http_method: None }
+    }
+
+    fn is_denied(decision: &ToolDecision) -> bool {
+        matches!(decision, ToolDecision::Deny(_))
+    }
+
+    #[tokio::test]
+    async fn deny_commands_covers_shell_tool_and_sessions() {
+        let policy = DenyCommands::new(&["rm -rf"]);
+        let shell = call("shell_tool", json!({ "command": "rm -rf build" }));
+        assert!(is_denied(&policy.decide(&shell).await));
+        let exec = json!({ "action": "exec", "session_id": "session-1", "command": "rm -rf ~" });
+        assert!(is_denied(&policy.decide(&call("shell_session", exec)).await));
+        let ls = call("shell_session", json!({ "action": "exec", "command": "ls" }));
+        assert!(matches!(policy.decide(&ls).await, ToolDecision::Approve));
+    }
+
+    #[tokio::test]
+    async fn deny_commands_matches_words_not_just_substrings() {
+        let policy = DenyCommands::new(&["rm -rf"]);
+        for command in ["rm -fr build", "\\rm -rf build", "r''m -rf build", "/bin/rm -r -f ."] {
+            let exec = call("shell_tool", json!({ "command": command }));
+            assert!(is_denied(&policy.decide(&exec).await), "{}", command);
+        }
+        let ls = call("shell_tool", json!({ "command": "rm -r build" }));
+        assert!(matches!(policy.decide(&ls).await, ToolDecision::Approve));
+    }
+
+    #[tokio::test]
+    async fn decisions_made_ahead_are_applied_once() {
+        let mut policies = ToolPolicies::new();
+        let asked = Arc::new(Mutex::new(0));
+        let counter = asked.clone();
+        policies.add(
+            Arc::new(move |call: &ToolCall| {
+                *counter.lock().unwrap() += 1;
+                assert_eq!(call.http_method.as_deref(), Some("DELETE"));
+                ToolDecision::Rewrite(json!({ "petId": 2 }))
+            })
+        );
+        policies.set_http_method("deletePet", "DELETE");
+
+        let args = r#"{"petId":1}"#;
+        let ahead = policies.decide_ahead("deletePet", args).await;
+        assert_eq!(policies.decision("deletePet", args).await, ahead);
+        assert_eq!(*asked.lock().unwrap(), 1);
+        // Without a decision left, the call is decided again
+        policies.decision("deletePet", args).await;
+        assert_eq!(*asked.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn deny_hosts_matches_subdomains() {
+        let policy = DenyHosts::new(&["*.internal", "localhost"]);
+        let internal = call("rest_api", json!({ "url": "http://db.corp.internal/x" }));
+        assert!(is_denied(&policy.decide(&internal).await));
+        let local = call("link_to_markdown", json!({ "url": "http://LOCALHOST:8080" }));
+        assert!(is_denied(&policy.decide(&local).await));
+        let public = call("rest_api", json!({ "url": "https://internal.example.com" }));
+        assert!(matches!(policy.decide(&public).await, ToolDecision::Approve));
+    }
+
+    #[tokio::test]
+    async fn first_deny_wins_and_aborts_are_remembered() {
+        let mut policies = ToolPolicies::new();
+        policies.add(
+            Arc::new(|call: &ToolCall| {
+                let mut args = call.args.clone();
+                args["command"] = json!("ls -la");
+                ToolDecision::Rewrite(args)
+            })
+        );
+        let rewritten = policies.decide(&call("shell_tool", json!({ "command": "ls" }))).await;
+        assert!(matches!(rewritten, ToolDecision::Rewrite(args) if args["command"] == "ls -la"));
+
+        policies.add(Arc::new(|_: &ToolCall| ToolDecision::Abort("stop".to_string())));
+        policies.add(Arc::new(|_: &ToolCall| ToolDecision::Deny("unreachable".to_string())));
+        let aborted = policies.decide(&call("shell_tool", json!({ "command": "ls" }))).await;
+        assert!(matches!(aborted, ToolDecision::Abort(reason) if reason == "stop"));
+        assert_eq!(policies.aborted().as_deref(), Some("stop"));
+        policies.reset();
+        assert_eq!(policies.aborted(), None);
+    }
+}
@@ -0,0 +1,302 @@
+use async_trait::async_trait;
+use rig::embeddings::EmbeddingModel;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{ BTreeSet, HashMap, HashSet };
+use std::hash::{ Hash, Hasher };
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::agent::agent::{ Message, MessageRole };
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecallError {
+    #[error("Embedding error: {0}")] EmbeddingError(String),
+}
+
+/// Turns text into a vector used to compare messages semantically
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, RecallError>;
+}
+
+/// Embedder backed by any rig embedding model (OpenAI, Gemini, Ollama...)
+pub struct RigEmbedder<M: EmbeddingModel> {
+    model: M,
+}
+
+impl<M: EmbeddingModel> RigEmbedder<M> {
+    pub fn new(model: M) -> Self {
+        Self { model }
+    }
+}
+
+#[async_trait]
+impl<M: EmbeddingModel + Send + Sync> Embedder for RigEmbedder<M> {
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, RecallError> {
+        let embedding = self.model
+            .embed_text(text).await
+            .map_err(|e| RecallError::EmbeddingError(e.to_string()))?;
+        Ok(embedding.vec)
+    }
+}
+
+/// Local, deterministic bag-of-words embedder. Needs no network access, which makes
+/// it suitable for tests and offline use.
+pub struct HashEmbedder {
+    pub dimensions: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions }
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+#[async_trait]
+impl Embedder for HashEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f64>, RecallError> {
+        let mut vector = vec![0.0; self.dimensions];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            let mut hasher = DefaultHasher::new();
+            word.to_lowercase().hash(&mut hasher);
+            let bucket = (hasher.finish() as usize) % self.dimensions;
+            vector[bucket] += 1.0;
+        }
+        Ok(vector)
+    }
+}
+
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| x * y)
+        .sum();
+    let norm_a = a
+        .iter()
+        .map(|x| x * x)
+        .sum::<f64>()
+        .sqrt();
+    let norm_b = b
+        .iter()
+        .map(|x| x * x)
+        .sum::<f64>()
+        .sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Selects the past messages most relevant to a prompt instead of replaying the
+/// whole history. Embeddings are cached by message content, so they stay valid when the
+/// history is resumed or compacted.
+pub struct SemanticRecall {
+    pub embedder: Arc<dyn Embedder>,
+    pub top_k: usize,
+    /// Number of most recent messages that are always sent to keep the conversation coherent
+    pub keep_recent: usize,
+    embeddings: Mutex<HashMap<u64, Vec<f64>>>,
+}
+
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl SemanticRecall {
+    pub fn new(embedder: Arc<dyn Embedder>, top_k: usize) -> Self {
+        Self {
+            embedder,
+            top_k,
+            keep_recent: 2,
+            embeddings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn keep_recent(mut self, keep_recent: usize) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Drops cached embeddings, e.g. after the history was compacted
+    pub async fn reset(&self) {
+        self.embeddings.lock().await.clear();
+    }
+
+    /// Returns the top-k messages for `prompt` plus the most recent ones, in chronological
+    /// order. A selected question brings its answer along and vice versa, the recent window
+    /// starts at a question and summaries of compacted history are always kept.
+    pub async fn select(
+        &self,
+        messages: &[Message],
+        prompt: &str
+    ) -> Result<Vec<Message>, RecallError> {
+        if messages.len() <= self.top_k + self.keep_recent {
+            return Ok(messages.to_vec());
+        }
+
+        let mut embeddings = self.embeddings.lock().await;
+        let hashes: Vec<u64> = messages
+            .iter()
+            .map(|message| content_hash(&message.message))
+            .collect();
+        for (message, hash) in messages.iter().zip(&hashes) {
+            if !embeddings.contains_key(hash) {
+                embeddings.insert(*hash, self.embedder.embed(&message.message).await?);
+            }
+        }
+        // Forget messages that left the history, e.g. after compaction
+        let current: HashSet<&u64> = hashes.iter().collect();
+        embeddings.retain(|hash, _| current.contains(hash));
+
+        let query = self.embedder.embed(prompt).await?;
+        let mut recent_start = messages.len() - self.keep_recent;
+        while recent_start > 0 && matches!(messages[recent_start].role, MessageRole::Assistant) {
+            recent_start -= 1;
+        }
+        let is_summary = |index: usize| matches!(messages[index].role, MessageRole::Summary);
+        let mut scored: Vec<(usize, f64)> = hashes[..recent_start]
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !is_summary(*index))
+            .map(|(index, hash)| (index, cosine_similarity(&query, &embeddings[hash])))
+            .collect();
+        scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        let is_exchange = |question: usize| {
+            question + 1 < messages.len() &&
+                matches!(messages[question].role, MessageRole::User) &&
+                matches!(messages[question + 1].role, MessageRole::Assistant)
+        };
+        let mut selected: BTreeSet<usize> = (0..recent_start).filter(|i| is_summary(*i)).collect();
+        for (index, _) in scored.into_iter().take(self.top_k) {
+            selected.insert(index);
+            if is_exchange(index) {
+                selected.insert(index + 1);
+            } else if index > 0 && is_exchange(index - 1) {
+                selected.insert(index - 1);
+            }
+        }
+        selected.extend(recent_start..messages.len());
+
+        Ok(
+            selected
+                .into_iter()
+                .map(|index| messages[index].clone())
+                .collect()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(question: &str, answer: &str) -> Vec<Message> {
+        vec![
+            Message::new(MessageRole::User, question.to_string()),
+            Message::new(MessageRole::Assistant, answer.to_string())
+        ]
+    }
+
+    fn recall() -> SemanticRecall {
+        SemanticRecall::new(Arc::new(HashEmbedder::default()), 1).keep_recent(2)
+    }
+
+    fn texts(messages: &[Message]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.message.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn selects_whole_exchanges() {
+        let messages = [
+            exchange("What is the capital of France?", "Paris."),
+            exchange("Recommend a pasta recipe", "Try carbonara with guanciale."),
+            exchange("How tall is Everest?", "About 8849 meters."),
+        ].concat();
+
+        let selected = recall().select(&messages, "pasta recipe").await.unwrap();
+        assert_eq!(texts(&selected), [
+            "Recommend a pasta recipe",
+            "Try carbonara with guanciale.",
+            "How tall is Everest?",
+            "About 8849 meters.",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn replaced_history_of_the_same_length_is_embedded_again() {
+        let recall = recall();
+        let first = [
+            exchange("Recommend a pasta recipe", "Try carbonara."),
+            exchange("What is the capital of France?", "Paris."),
+            exchange("How tall is Everest?", "About 8849 meters."),
+        ].concat();
+        recall.select(&first, "pasta").await.unwrap();
+
+        let replaced = [
+            exchange("What is the capital of Spain?", "Madrid."),
+            exchange("Which pasta goes with pesto?", "Trofie."),
+            exchange("How deep is the Mariana trench?", "About 11 km."),
+        ].concat();
+        let selected = recall.select(&replaced, "pasta").await.unwrap();
+        assert_eq!(texts(&selected), [
+            "Which pasta goes with pesto?",
+            "Trofie.",
+            "How deep is the Mariana trench?",
+            "About 11 km.",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn summaries_are_always_kept() {
+        let mut messages = vec![
+            Message::new(MessageRole::Summary, "Earlier the user bought a red car.".to_string())
+        ];
+        messages.extend(
+            [
+                exchange("Recommend a pasta recipe", "Try carbonara."),
+                exchange("What is the capital of France?", "Paris."),
+                exchange("How tall is Everest?", "About 8849 meters."),
+            ].concat()
+        );
+
+        let selected = recall().select(&messages, "pasta recipe").await.unwrap();
+        assert_eq!(texts(&selected), [
+            "Earlier the user bought a red car.",
+            "Recommend a pasta recipe",
+            "Try carbonara.",
+            "How tall is Everest?",
+            "About 8849 meters.",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn recent_window_starts_at_a_question() {
+        let messages = [
+            exchange("Recommend a pasta recipe", "Try carbonara."),
+            exchange("What is the capital of France?", "Paris."),
+            exchange("How tall is Everest?", "About 8849 meters."),
+        ].concat();
+
+        let recall = SemanticRecall::new(Arc::new(HashEmbedder::default()), 1).keep_recent(1);
+        let selected = recall.select(&messages, "pasta recipe").await.unwrap();
+        assert_eq!(texts(&selected), [
+            "Recommend a pasta recipe",
+            "Try carbonara.",
+            "How tall is Everest?",
+            "About 8849 meters.",
+        ]);
+    }
+}
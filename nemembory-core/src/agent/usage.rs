@@ -0,0 +1,146 @@
+use chrono::{ DateTime, Utc };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use std::sync::{ Arc, Mutex };
+
+/// Input and output tokens reported by the provider
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn new(input_tokens: u64, output_tokens: u64) -> Self {
+        Self { input_tokens, output_tokens }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+impl From<rig::completion::Usage> for TokenUsage {
+    fn from(usage: rig::completion::Usage) -> Self {
+        Self { input_tokens: usage.input_tokens, output_tokens: usage.output_tokens }
+    }
+}
+
+/// Price of a model in dollars per million tokens
+#[derive(Clone, Copy, Debug)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+/// Per-model prices used to turn token usage into spend
+#[derive(Clone, Debug, Default)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new() -> Self {
+        Self { prices: HashMap::new() }
+    }
+
+    pub fn with_price(mut self, model: &str, input_per_million: f64, output_per_million: f64) -> Self {
+        self.prices.insert(model.to_string(), ModelPrice { input_per_million, output_per_million });
+        self
+    }
+
+    /// Cost in dollars, or `None` when the model has no price
+    pub fn cost(&self, model: &str, usage: &TokenUsage) -> Option<f64> {
+        self.prices.get(model).map(|price| {
+            ((usage.input_tokens as f64) * price.input_per_million +
+                (usage.output_tokens as f64) * price.output_per_million) /
+                1_000_000.0
+        })
+    }
+}
+
+/// Usage of a single `run`, broken down per model turn
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RunUsage {
+    pub agent_name: String,
+    pub model: String,
+    pub turns: Vec<TokenUsage>,
+    pub total: TokenUsage,
+    pub cost: Option<f64>,
+    pub time_stamp: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct UsageState {
+    current_turns: Vec<TokenUsage>,
+    session: TokenUsage,
+    session_cost: f64,
+    runs: Vec<RunUsage>,
+}
+
+/// Aggregates token usage per turn, per run and per session. Clones share the same totals,
+/// so the tracker can be handed to hooks while the agent keeps reading it.
+#[derive(Clone, Default)]
+pub struct UsageTracker {
+    state: Arc<Mutex<UsageState>>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the usage of one model turn in the current run
+    pub fn record_turn(&self, usage: TokenUsage) {
+        self.state.lock().unwrap().current_turns.push(usage);
+    }
+
+    /// Closes the current run and adds it to the session totals
+    pub fn finish_run(&self, agent_name: &str, model: &str, prices: &PriceTable) -> RunUsage {
+        let mut state = self.state.lock().unwrap();
+        let turns = std::mem::take(&mut state.current_turns);
+        let mut total = TokenUsage::default();
+        for turn in &turns {
+            total += *turn;
+        }
+
+        let run = RunUsage {
+            agent_name: agent_name.to_string(),
+            model: model.to_string(),
+            cost: prices.cost(model, &total),
+            turns,
+            total,
+            time_stamp: Utc::now(),
+        };
+
+        state.session += total;
+        state.session_cost += run.cost.unwrap_or(0.0);
+        state.runs.push(run.clone());
+        run
+    }
+
+    pub fn session_usage(&self) -> TokenUsage {
+        self.state.lock().unwrap().session
+    }
+
+    /// Session spend in dollars; runs of unpriced models count as zero
+    pub fn session_cost(&self) -> f64 {
+        self.state.lock().unwrap().session_cost
+    }
+
+    pub fn runs(&self) -> Vec<RunUsage> {
+        self.state.lock().unwrap().runs.clone()
+    }
+
+    pub fn last_run(&self) -> Option<RunUsage> {
+        self.state.lock().unwrap().runs.last().cloned()
+    }
+}
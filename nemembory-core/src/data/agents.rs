@@ -1,6 +1,7 @@
 use async_trait::async_trait;
-use chrono::{Date, DateTime, Utc};
-use sqlx::{PgPool, Pool, prelude::FromRow};
+use sqlx::{ PgPool, prelude::FromRow };
+
+use crate::agent::model::ModelError;
 
 #[derive(serde::Serialize, FromRow, serde::Deserialize, Debug, Clone)]
 pub struct Agent {
@@ -10,7 +11,7 @@ pub struct Agent {
     pub system_prompt: String,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[derive(serde::Serialize, FromRow, serde::Deserialize, Debug, Clone)]
 pub struct Tool {
     pub name: String,
     pub description: String,
@@ -21,6 +22,12 @@ pub trait AgentPersistence {
     async fn save_agent(&self, agent: Agent) -> Result<(), DbError>;
     async fn load_agents(&self) -> Result<Vec<Agent>, DbError>;
     async fn get_agent(&self, id: i8) -> Result<Agent, DbError>;
+    async fn get_agent_by_code(&self, code: &str) -> Result<Agent, DbError>;
+    async fn get_agent_tools(&self, code: &str) -> Result<Vec<Tool>, DbError>;
+    async fn save_tool(&self, tool: Tool) -> Result<(), DbError>;
+    async fn load_tools(&self) -> Result<Vec<Tool>, DbError>;
+    async fn grant_tool(&self, code: &str, tool_name: &str) -> Result<(), DbError>;
+    async fn revoke_tool(&self, code: &str, tool_name: &str) -> Result<(), DbError>;
     async fn add_agent(&self, agent: Agent) -> Result<Agent, DbError>;
 }
 
@@ -67,6 +74,71 @@ impl AgentPersistence for DbAgentStore {
         Ok(agent)
     }
 
+    async fn get_agent_by_code(&self, code: &str) -> Result<Agent, DbError> {
+        let query = "SELECT id, code, display_name, system_prompt FROM agents WHERE code = $1";
+        let agent = sqlx::query_as::<_, Agent>(query)
+            .bind(code)
+            .fetch_one(&self.db_pool)
+            .await?;
+        Ok(agent)
+    }
+
+    async fn get_agent_tools(&self, code: &str) -> Result<Vec<Tool>, DbError> {
+        let query = r#"SELECT t.name, t.description FROM tools t
+            JOIN agent_tools at ON at.tool_name = t.name
+            JOIN agents a ON a.id = at.agent_id
+            WHERE a.code = $1
+            ORDER BY t.name"#;
+        let tools = sqlx::query_as::<_, Tool>(query)
+            .bind(code)
+            .fetch_all(&self.db_pool)
+            .await?;
+        Ok(tools)
+    }
+
+    async fn save_tool(&self, tool: Tool) -> Result<(), DbError> {
+        let query = r#"INSERT INTO tools (name, description) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET description = EXCLUDED.description"#;
+        sqlx::query(query)
+            .bind(&tool.name)
+            .bind(&tool.description)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_tools(&self) -> Result<Vec<Tool>, DbError> {
+        let query = "SELECT name, description FROM tools ORDER BY name";
+        let tools = sqlx::query_as::<_, Tool>(query)
+            .fetch_all(&self.db_pool)
+            .await?;
+        Ok(tools)
+    }
+
+    async fn grant_tool(&self, code: &str, tool_name: &str) -> Result<(), DbError> {
+        let query = r#"INSERT INTO agent_tools (agent_id, tool_name)
+            SELECT id, $2 FROM agents WHERE code = $1
+            ON CONFLICT DO NOTHING"#;
+        self.get_agent_by_code(code).await?;
+        sqlx::query(query)
+            .bind(code)
+            .bind(tool_name)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_tool(&self, code: &str, tool_name: &str) -> Result<(), DbError> {
+        let query = r#"DELETE FROM agent_tools
+            WHERE tool_name = $2 AND agent_id = (SELECT id FROM agents WHERE code = $1)"#;
+        sqlx::query(query)
+            .bind(code)
+            .bind(tool_name)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
     async fn add_agent(&self, agent: Agent) -> Result<Agent, DbError> {
         let query = "INSERT INTO agents (code, display_name, system_prompt) VALUES ($1, $2, $3) RETURNING id, code, display_name, system_prompt";
         let new_agent = sqlx::query_as::<_, Agent>(query)
@@ -83,6 +155,8 @@ impl AgentPersistence for DbAgentStore {
 pub enum DbError {
     #[error("Sql error: {0}")]
     SqlError(String),
+    #[error(transparent)]
+    Model(#[from] ModelError),
 }
 
 impl From<sqlx::Error> for DbError {
@@ -90,3 +164,52 @@ impl From<sqlx::Error> for DbError {
         DbError::SqlError(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::test_pool;
+
+    async fn tool_names(store: &DbAgentStore, code: &str) -> Vec<String> {
+        let tools = store.get_agent_tools(code).await.unwrap();
+        tools.into_iter().map(|tool| tool.name).collect()
+    }
+
+    #[tokio::test]
+    async fn tools_are_granted_once_and_revoked() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let store = DbAgentStore { db_pool: pool };
+        let code = format!("grant-test-{}", std::process::id());
+        sqlx::query("DELETE FROM agents WHERE code = $1")
+            .bind(&code)
+            .execute(&store.db_pool).await
+            .unwrap();
+        store
+            .add_agent(Agent {
+                id: 0,
+                code: code.clone(),
+                display_name: "Grant test".to_string(),
+                system_prompt: String::new(),
+            }).await
+            .unwrap();
+
+        store.grant_tool(&code, "web_search").await.unwrap();
+        store.grant_tool(&code, "get_date").await.unwrap();
+        store.grant_tool(&code, "get_date").await.unwrap();
+        assert_eq!(tool_names(&store, &code).await, ["get_date", "web_search"]);
+
+        store.revoke_tool(&code, "web_search").await.unwrap();
+        store.revoke_tool(&code, "web_search").await.unwrap();
+        assert_eq!(tool_names(&store, &code).await, ["get_date"]);
+
+        assert!(store.grant_tool(&code, "no_such_tool").await.is_err());
+        assert!(store.grant_tool("no-such-agent", "get_date").await.is_err());
+
+        sqlx::query("DELETE FROM agents WHERE code = $1")
+            .bind(&code)
+            .execute(&store.db_pool).await
+            .unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use chrono::{ DateTime, Utc };
+use sqlx::prelude::FromRow;
+
+use crate::agent::agent::{ Message, MessageRole };
+use crate::agent::usage::RunUsage;
+use crate::data::agents::{ DbAgentStore, DbError };
+
+#[derive(serde::Serialize, FromRow, serde::Deserialize, Debug, Clone)]
+pub struct Conversation {
+    pub id: i32,
+    pub agent_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+struct MessageRow {
+    role: String,
+    content: String,
+    time_stamp: DateTime<Utc>,
+}
+
+impl TryFrom<MessageRow> for Message {
+    type Error = DbError;
+
+    fn try_from(row: MessageRow) -> Result<Self, Self::Error> {
+        let role = match row.role.as_str() {
+            "User" => MessageRole::User,
+            "Assistant" => MessageRole::Assistant,
+            "Summary" => MessageRole::Summary,
+            other => {
+                return Err(DbError::SqlError(format!("Unknown message role: {}", other)));
+            }
+        };
+        Ok(Message { role, message: row.content, time_stamp: row.time_stamp })
+    }
+}
+
+#[async_trait]
+pub trait ConversationStore {
+    async fn create_conversation(&self, agent_name: &str) -> Result<Conversation, DbError>;
+    async fn latest_conversation(&self, agent_name: &str) -> Result<Option<Conversation>, DbError>;
+    async fn save_message(&self, conversation_id: i32, message: &Message) -> Result<(), DbError>;
+    async fn load_messages(&self, conversation_id: i32) -> Result<Vec<Message>, DbError>;
+    async fn save_usage(&self, conversation_id: i32, usage: &RunUsage) -> Result<(), DbError>;
+}
+
+#[async_trait]
+impl ConversationStore for DbAgentStore {
+    async fn create_conversation(&self, agent_name: &str) -> Result<Conversation, DbError> {
+        let query =
+            "INSERT INTO conversations (agent_name) VALUES ($1) RETURNING id, agent_name, created_at";
+        let conversation = sqlx::query_as::<_, Conversation>(query)
+            .bind(agent_name)
+            .fetch_one(&self.db_pool).await?;
+        Ok(conversation)
+    }
+
+    async fn latest_conversation(&self, agent_name: &str) -> Result<Option<Conversation>, DbError> {
+        let query =
+            "SELECT id, agent_name, created_at FROM conversations WHERE agent_name = $1 ORDER BY created_at DESC, id DESC LIMIT 1";
+        let conversation = sqlx::query_as::<_, Conversation>(query)
+            .bind(agent_name)
+            .fetch_optional(&self.db_pool).await?;
+        Ok(conversation)
+    }
+
+    async fn save_message(&self, conversation_id: i32, message: &Message) -> Result<(), DbError> {
+        let query =
+            "INSERT INTO messages (conversation_id, role, content, time_stamp) VALUES ($1, $2, $3, $4)";
+        sqlx::query(query)
+            .bind(conversation_id)
+            .bind(format!("{:?}", message.role))
+            .bind(&message.message)
+            .bind(message.time_stamp)
+            .execute(&self.db_pool).await?;
+        Ok(())
+    }
+
+    async fn load_messages(&self, conversation_id: i32) -> Result<Vec<Message>, DbError> {
+        let query =
+            "SELECT role, content, time_stamp FROM messages WHERE conversation_id = $1 ORDER BY time_stamp, id";
+        let rows = sqlx::query_as::<_, MessageRow>(query)
+            .bind(conversation_id)
+            .fetch_all(&self.db_pool).await?;
+        rows.into_iter().map(Message::try_from).collect()
+    }
+
+    async fn save_usage(&self, conversation_id: i32, usage: &RunUsage) -> Result<(), DbError> {
+        let query =
+            r#"INSERT INTO usage (conversation_id, agent_name, model, turns, input_tokens, output_tokens, cost, time_stamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#;
+        sqlx::query(query)
+            .bind(conversation_id)
+            .bind(&usage.agent_name)
+            .bind(&usage.model)
+            .bind(usage.turns.len() as i32)
+            .bind(usage.total.input_tokens as i64)
+            .bind(usage.total.output_tokens as i64)
+            .bind(usage.cost)
+            .bind(usage.time_stamp)
+            .execute(&self.db_pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::test_pool;
+    use chrono::TimeZone;
+
+    fn message(role: MessageRole, text: &str, second: u32) -> Message {
+        let time_stamp = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, second).unwrap();
+        Message { role, message: text.to_string(), time_stamp }
+    }
+
+    #[tokio::test]
+    async fn messages_round_trip_in_order() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let store = DbAgentStore { db_pool: pool };
+        let agent = format!("conversation-test-{}", std::process::id());
+        let conversation = store.create_conversation(&agent).await.unwrap();
+        let latest = store.latest_conversation(&agent).await.unwrap().unwrap();
+        assert_eq!(latest.id, conversation.id);
+
+        // Saved out of order; messages with the same time stamp keep their insertion order
+        store.save_message(conversation.id, &message(MessageRole::User, "Hi", 1)).await.unwrap();
+        let summary = message(MessageRole::Summary, "Earlier talk", 0);
+        store.save_message(conversation.id, &summary).await.unwrap();
+        let answer = message(MessageRole::Assistant, "Hello", 1);
+        store.save_message(conversation.id, &answer).await.unwrap();
+
+        let messages = store.load_messages(conversation.id).await.unwrap();
+        let loaded: Vec<_> = messages
+            .iter()
+            .map(|m| (format!("{:?}", m.role), m.message.as_str(), m.time_stamp))
+            .collect();
+        assert_eq!(loaded, [
+            ("Summary".to_string(), "Earlier talk", summary.time_stamp),
+            ("User".to_string(), "Hi", answer.time_stamp),
+            ("Assistant".to_string(), "Hello", answer.time_stamp),
+        ]);
+
+        let next = store.create_conversation(&agent).await.unwrap();
+        assert_eq!(store.latest_conversation(&agent).await.unwrap().unwrap().id, next.id);
+        assert!(store.load_messages(next.id).await.unwrap().is_empty());
+
+        sqlx::query("DELETE FROM conversations WHERE agent_name = $1")
+            .bind(&agent)
+            .execute(&store.db_pool).await
+            .unwrap();
+        assert!(store.latest_conversation(&agent).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        let row = MessageRow {
+            role: "System".to_string(),
+            content: String::new(),
+            time_stamp: Utc::now(),
+        };
+        assert!(matches!(Message::try_from(row), Err(DbError::SqlError(_))));
+    }
+}
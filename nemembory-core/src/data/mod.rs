@@ -1,2 +1,12 @@
 pub mod agents;
+pub mod conversations;
 pub use agents::{ Agent, Tool, AgentPersistence, DbAgentStore, DbError };
+pub use conversations::{ Conversation, ConversationStore };
+
+/// Pool for the database tests. They only run when `DATABASE_URL` points at a database
+/// created from `init/init.sql`, such as the one in docker-compose.yml.
+#[cfg(test)]
+pub(crate) async fn test_pool() -> Option<sqlx::PgPool> {
+    let url = std::env::var("DATABASE_URL").ok()?;
+    Some(sqlx::PgPool::connect(&url).await.expect("DATABASE_URL should be reachable"))
+}
@@ -0,0 +1,81 @@
+use crate::agent::agent::{ Message, MessageHandler };
+use crate::agent::usage::RunUsage;
+use crate::data::ConversationStore;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Writes every message of a conversation to Postgres so the session can be resumed
+pub struct DbMessageHandler {
+    store: Arc<dyn ConversationStore + Send + Sync>,
+    conversation_id: i32,
+}
+
+impl DbMessageHandler {
+    pub fn new(store: Arc<dyn ConversationStore + Send + Sync>, conversation_id: i32) -> Self {
+        Self { store, conversation_id }
+    }
+}
+
+#[async_trait]
+impl MessageHandler for DbMessageHandler {
+    async fn handle_message(&self, message: Message) -> Result<(), std::io::Error> {
+        self.store
+            .save_message(self.conversation_id, &message).await
+            .map_err(|e| std::io::Error::other(e.to_string()))
+    }
+
+    async fn handle_usage(&self, usage: RunUsage) -> Result<(), std::io::Error> {
+        self.store
+            .save_usage(self.conversation_id, &usage).await
+            .map_err(|e| std::io::Error::other(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::agent::MessageRole;
+    use crate::agent::usage::TokenUsage;
+    use crate::data::{ DbAgentStore, test_pool };
+
+    #[tokio::test]
+    async fn messages_and_usage_are_saved_to_the_conversation() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let store = Arc::new(DbAgentStore { db_pool: pool });
+        let agent = format!("db-handler-test-{}", std::process::id());
+        let conversation = store.create_conversation(&agent).await.unwrap();
+        let handler = DbMessageHandler::new(store.clone(), conversation.id);
+
+        handler.handle_message(Message::new(MessageRole::User, "Hi".to_string())).await.unwrap();
+        let answer = Message::new(MessageRole::Assistant, "Hello".to_string());
+        handler.handle_message(answer).await.unwrap();
+        handler
+            .handle_usage(RunUsage {
+                agent_name: agent.clone(),
+                model: "gpt-test".to_string(),
+                turns: vec![TokenUsage::new(10, 2), TokenUsage::new(20, 3)],
+                total: TokenUsage::new(30, 5),
+                cost: Some(0.5),
+                time_stamp: chrono::Utc::now(),
+            }).await
+            .unwrap();
+
+        let messages = store.load_messages(conversation.id).await.unwrap();
+        let texts: Vec<_> = messages.iter().map(|m| m.message.as_str()).collect();
+        assert_eq!(texts, ["Hi", "Hello"]);
+        let query =
+            "SELECT model, turns, input_tokens, output_tokens, cost FROM usage WHERE conversation_id = $1";
+        let usage: (String, i32, i64, i64, Option<f64>) = sqlx::query_as(query)
+            .bind(conversation.id)
+            .fetch_one(&store.db_pool).await
+            .unwrap();
+        assert_eq!(usage, ("gpt-test".to_string(), 2, 30, 5, Some(0.5)));
+
+        sqlx::query("DELETE FROM conversations WHERE id = $1")
+            .bind(conversation.id)
+            .execute(&store.db_pool).await
+            .unwrap();
+    }
+}
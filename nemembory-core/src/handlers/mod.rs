@@ -1,2 +1,4 @@
 pub mod filehandler;
+pub mod dbhandler;
 pub use filehandler::FileHandler;
+pub use dbhandler::DbMessageHandler;
@@ -1,19 +1,17 @@
-use std::collections::HashMap;
+use async_trait::async_trait;
 
-// Callback to handle tool call metadata from the agent hook
-pub fn log_tool_call(params: HashMap<String, String>) {
-    if let Some(tool_name) = params.get("tool_name") {
-        if let Some(args) = params.get("args") {
-            println!("Tool called: {} with args: {}", tool_name, args);
-        }
+use crate::agent::observer::{ AgentObserver, ToolCallEvent, ToolResultEvent };
+
+/// Prints tool calls and tool results to stdout
+pub struct ConsoleLogger;
+
+#[async_trait]
+impl AgentObserver for ConsoleLogger {
+    async fn on_tool_call(&self, event: &ToolCallEvent) {
+        println!("Tool called: {} with args: {}", event.tool_name, event.args);
     }
-}
 
-// Callback to handle tool call result metadata from the agent hook
-pub fn log_tool_call_result(params: HashMap<String, String>) {
-    if let Some(tool_name) = params.get("tool_name") {
-        if let Some(result) = params.get("result") {
-            println!("Tool result: {} returned: {}", tool_name, result);
-        }
+    async fn on_tool_result(&self, event: &ToolResultEvent) {
+        println!("Tool result: {} returned: {}", event.tool_name, event.result);
     }
 }
@@ -1,6 +1,5 @@
 pub mod log;
 pub mod save;
-pub use log::log_tool_call;
-pub use log::log_tool_call_result;
+pub use log::ConsoleLogger;
 pub use save::WriteToolLogToFile;
 pub use save::WriteToolResultToFile;
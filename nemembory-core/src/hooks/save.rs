@@ -1,10 +1,11 @@
-use std::collections::HashMap;
-use std::fs;
 use std::path::Path;
 
+use async_trait::async_trait;
 use chrono::{ DateTime, Utc };
-use rig::embeddings::tool;
 use serde::{ Deserialize, Serialize };
+use tokio::fs;
+
+use crate::agent::observer::{ AgentObserver, ToolCallEvent, ToolResultEvent };
 
 pub struct WriteToolLogToFile {
     pub path: String,
@@ -13,13 +14,19 @@ pub struct WriteToolLogToFile {
 #[derive(Serialize, Deserialize, Debug)]
 struct ToolLog {
     name: String,
-    args: String,
+    args: serde_json::Value,
+    turn: usize,
     timestamp: DateTime<Utc>,
 }
 
 impl ToolLog {
-    pub fn new(name: &str, args: &str) -> Self {
-        Self { name: name.to_owned(), args: args.to_owned(), timestamp: Utc::now() }
+    pub fn new(event: &ToolCallEvent) -> Self {
+        Self {
+            name: event.tool_name.clone(),
+            args: event.args.clone(),
+            turn: event.turn,
+            timestamp: event.time_stamp,
+        }
     }
 }
 
@@ -34,11 +41,10 @@ impl WriteToolLogToFile {
         Self { path: file_path.to_string_lossy().to_string() }
     }
 
-    // Callback to handle tool call metadata from the agent hook
-    pub fn write_to_file(&self, params: HashMap<String, String>) {
-        dbg!(&self.path);
+    // Appends a tool call to the log file
+    pub async fn write_to_file(&self, event: &ToolCallEvent) {
         let mut tool_logs: Vec<ToolLog> = if Path::new(&self.path).exists() {
-            match fs::read_to_string(&self.path) {
+            match fs::read_to_string(&self.path).await {
                 Ok(contents) if !contents.trim().is_empty() => {
                     serde_json::from_str(&contents).unwrap_or_else(|e| {
                         eprintln!("Failed to deserialize tool logs: {}", e);
@@ -55,29 +61,29 @@ impl WriteToolLogToFile {
             Vec::new()
         };
 
-        dbg!(&params);
-        // Build new ToolLog from tool_name and content
-        if let (Some(tool_name), Some(args)) = (params.get("tool_name"), params.get("args")) {
-            let new_log = ToolLog::new(tool_name, args);
+        tool_logs.push(ToolLog::new(event));
 
-            // Update the vector with the new ToolLog
-            tool_logs.push(new_log);
-
-            // Save the file with the updated vector
-            match serde_json::to_string_pretty(&tool_logs) {
-                Ok(json) => {
-                    if let Err(e) = fs::write(&self.path, json) {
-                        eprintln!("Failed to write to file {}: {}", &self.path, e);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to serialize tool logs: {}", e);
+        // Save the file with the updated vector
+        match serde_json::to_string_pretty(&tool_logs) {
+            Ok(json) => {
+                if let Err(e) = fs::write(&self.path, json).await {
+                    eprintln!("Failed to write to file {}: {}", &self.path, e);
                 }
             }
+            Err(e) => {
+                eprintln!("Failed to serialize tool logs: {}", e);
+            }
         }
     }
 }
 
+#[async_trait]
+impl AgentObserver for WriteToolLogToFile {
+    async fn on_tool_call(&self, event: &ToolCallEvent) {
+        self.write_to_file(event).await;
+    }
+}
+
 pub struct WriteToolResultToFile {
     pub path: String,
 }
@@ -85,18 +91,20 @@ pub struct WriteToolResultToFile {
 #[derive(Serialize, Deserialize, Debug)]
 struct ToolResultLog {
     name: String,
-    args: String,
+    args: serde_json::Value,
     result: String,
+    turn: usize,
     timestamp: DateTime<Utc>,
 }
 
 impl ToolResultLog {
-    pub fn new(name: &str, args: &str, result: &str) -> Self {
+    pub fn new(event: &ToolResultEvent) -> Self {
         Self {
-            name: name.to_owned(),
-            args: args.to_owned(),
-            result: result.to_owned(),
-            timestamp: Utc::now(),
+            name: event.tool_name.clone(),
+            args: event.args.clone(),
+            result: event.result.clone(),
+            turn: event.turn,
+            timestamp: event.time_stamp,
         }
     }
 }
@@ -112,10 +120,10 @@ impl WriteToolResultToFile {
         Self { path: file_path.to_string_lossy().to_string() }
     }
 
-    // Callback to handle tool result metadata from the agent hook
-    pub fn write_to_file(&self, params: HashMap<String, String>) {
+    // Appends a tool result to the log file
+    pub async fn write_to_file(&self, event: &ToolResultEvent) {
         let mut tool_logs: Vec<ToolResultLog> = if Path::new(&self.path).exists() {
-            match fs::read_to_string(&self.path) {
+            match fs::read_to_string(&self.path).await {
                 Ok(contents) if !contents.trim().is_empty() => {
                     serde_json::from_str(&contents).unwrap_or_else(|e| {
                         eprintln!("Failed to deserialize tool result logs: {}", e);
@@ -132,30 +140,25 @@ impl WriteToolResultToFile {
             Vec::new()
         };
 
-        // Build new ToolResultLog from tool_name, args and result
-        if
-            let (Some(tool_name), Some(args), Some(result)) = (
-                params.get("tool_name"),
-                params.get("args"),
-                params.get("result"),
-            )
-        {
-            let new_log = ToolResultLog::new(tool_name, args, result);
-
-            // Update the vector with the new ToolResultLog
-            tool_logs.push(new_log);
-
-            // Save the file with the updated vector
-            match serde_json::to_string_pretty(&tool_logs) {
-                Ok(json) => {
-                    if let Err(e) = fs::write(&self.path, json) {
-                        eprintln!("Failed to write to file {}: {}", &self.path, e);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to serialize tool result logs: {}", e);
+        tool_logs.push(ToolResultLog::new(event));
+
+        // Save the file with the updated vector
+        match serde_json::to_string_pretty(&tool_logs) {
+            Ok(json) => {
+                if let Err(e) = fs::write(&self.path, json).await {
+                    eprintln!("Failed to write to file {}: {}", &self.path, e);
                 }
             }
+            Err(e) => {
+                eprintln!("Failed to serialize tool result logs: {}", e);
+            }
         }
     }
 }
+
+#[async_trait]
+impl AgentObserver for WriteToolResultToFile {
+    async fn on_tool_result(&self, event: &ToolResultEvent) {
+        self.write_to_file(event).await;
+    }
+}
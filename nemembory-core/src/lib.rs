@@ -3,7 +3,16 @@ pub mod tools;
 pub mod data;
 pub mod hooks;
 pub mod handlers;
+pub mod memory;
 
-pub use agent::{ build_runnable_agent, ModelProvider, RunnableAgent, NememboryAgent };
-pub use tools::{ RestApiTool, WebSearch, ShellTool, LinkToMarkdown };
-pub use data::{ Agent, Tool, AgentPersistence };
+pub use agent::{
+    build_runnable_agent,
+    ModelProvider,
+    RunnableAgent,
+    NememboryAgent,
+    NememboryAgentBuilder,
+};
+pub use tools::{ RestApiTool, WebSearch, ShellTool, LinkToMarkdown, ToolRegistry };
+pub use data::{ Agent, Tool, AgentPersistence, ConversationStore, DbAgentStore };
+pub use memory::{ MemoryStore, InMemoryStore, FileMemoryStore, DbMemoryStore };
+pub use tokio_util::sync::CancellationToken;
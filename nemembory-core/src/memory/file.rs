@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use serde::{ Deserialize, Serialize };
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::memory::store::{ MemoryError, MemoryFact, MemoryStore, rank_facts };
+
+/// Contents of the memory file. The id counter is stored with the facts so ids are never
+/// reused, even after `forget` removed the newest ones.
+#[derive(Serialize, Deserialize)]
+struct MemoryFile {
+    next_id: i64,
+    facts: Vec<MemoryFact>,
+}
+
+impl Default for MemoryFile {
+    fn default() -> Self {
+        Self { next_id: 1, facts: Vec::new() }
+    }
+}
+
+/// Files written before the counter existed hold a plain list of facts
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFile {
+    Current(MemoryFile),
+    Legacy(Vec<MemoryFact>),
+}
+
+/// Memory store that keeps every fact in a single JSON file
+pub struct FileMemoryStore {
+    pub path: String,
+    lock: Mutex<()>,
+}
+
+impl FileMemoryStore {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string(), lock: Mutex::new(()) }
+    }
+
+    async fn read_file(&self) -> Result<MemoryFile, MemoryError> {
+        if !fs::try_exists(&self.path).await? {
+            return Ok(MemoryFile::default());
+        }
+        let contents = fs::read_to_string(&self.path).await?;
+        if contents.trim().is_empty() {
+            return Ok(MemoryFile::default());
+        }
+        Ok(match serde_json::from_str(&contents)? {
+            StoredFile::Current(file) => file,
+            StoredFile::Legacy(facts) => {
+                let next_id = facts.iter().map(|f| f.id).max().unwrap_or(0) + 1;
+                MemoryFile { next_id, facts }
+            }
+        })
+    }
+
+    async fn write_file(&self, file: &MemoryFile) -> Result<(), MemoryError> {
+        let json = serde_json::to_string_pretty(file)?;
+        fs::write(&self.path, json).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MemoryStore for FileMemoryStore {
+    async fn remember(&self, agent_name: &str, content: &str) -> Result<MemoryFact, MemoryError> {
+        let _guard = self.lock.lock().await;
+        let mut file = self.read_file().await?;
+        let existing = file.facts
+            .iter()
+            .find(|fact| fact.agent_name == agent_name && fact.content == content);
+        if let Some(fact) = existing {
+            return Ok(fact.clone());
+        }
+        let fact = MemoryFact {
+            id: file.next_id,
+            agent_name: agent_name.to_string(),
+            content: content.to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        file.next_id += 1;
+        file.facts.push(fact.clone());
+        self.write_file(&file).await?;
+        Ok(fact)
+    }
+
+    async fn recall(
+        &self,
+        agent_name: &str,
+        query: &str,
+        limit: usize
+    ) -> Result<Vec<MemoryFact>, MemoryError> {
+        let _guard = self.lock.lock().await;
+        let facts = self
+            .read_file().await?
+            .facts.into_iter()
+            .filter(|fact| fact.agent_name == agent_name)
+            .collect();
+        Ok(rank_facts(facts, query, limit))
+    }
+
+    async fn forget(&self, agent_name: &str) -> Result<(), MemoryError> {
+        let _guard = self.lock.lock().await;
+        let mut file = self.read_file().await?;
+        file.facts.retain(|fact| fact.agent_name != agent_name);
+        self.write_file(&file).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(
+            format!("nemembory-{}-{}.json", name, std::process::id())
+        );
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    #[tokio::test]
+    async fn ids_are_not_reused_after_forget() {
+        let path = temp_path("ids");
+        let store = FileMemoryStore::new(&path);
+        store.remember("other", "The user likes tea").await.unwrap();
+        let newest = store.remember("agent", "The user lives in Lisbon").await.unwrap();
+        store.forget("agent").await.unwrap();
+
+        // A new store reads the counter back from the file
+        let store = FileMemoryStore::new(&path);
+        let fact = store.remember("agent", "The user works remotely").await.unwrap();
+        assert!(fact.id > newest.id);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn facts_are_deduplicated_and_recalled_per_agent() {
+        let path = temp_path("recall");
+        let store = FileMemoryStore::new(&path);
+        let first = store.remember("agent", "The user lives in Lisbon").await.unwrap();
+        let second = store.remember("agent", "The user lives in Lisbon").await.unwrap();
+        assert_eq!(first.id, second.id);
+        store.remember("other", "The user lives in Porto").await.unwrap();
+
+        let facts = store.recall("agent", "where does the user live", 10).await.unwrap();
+        assert_eq!(facts.len(), 1);
+        assert_eq!(facts[0].content, "The user lives in Lisbon");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_files_without_a_counter() {
+        let path = temp_path("legacy");
+        let legacy =
+            r#"[{"id":7,"agent_name":"agent","content":"The user likes tea",
+            "created_at":"2025-01-01T00:00:00Z"}]"#;
+        std::fs::write(&path, legacy).unwrap();
+
+        let store = FileMemoryStore::new(&path);
+        let fact = store.remember("agent", "The user works remotely").await.unwrap();
+        assert_eq!(fact.id, 8);
+        assert_eq!(store.recall("agent", "tea", 10).await.unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use std::sync::RwLock;
+use std::sync::atomic::{ AtomicI64, Ordering };
+
+use crate::memory::store::{ MemoryError, MemoryFact, MemoryStore, rank_facts };
+
+/// Process-local memory store, mainly useful for short-lived agents and examples
+pub struct InMemoryStore {
+    facts: RwLock<Vec<MemoryFact>>,
+    /// Ids are never reused, even after `forget`
+    next_id: AtomicI64,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self { facts: RwLock::new(Vec::new()), next_id: AtomicI64::new(1) }
+    }
+}
+
+impl Default for InMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl MemoryStore for InMemoryStore {
+    async fn remember(&self, agent_name: &str, content: &str) -> Result<MemoryFact, MemoryError> {
+        let mut facts = self.facts.write().unwrap();
+        let existing = facts
+            .iter()
+            .find(|fact| fact.agent_name == agent_name && fact.content == content);
+        if let Some(fact) = existing {
+            return Ok(fact.clone());
+        }
+        let fact = MemoryFact {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            agent_name: agent_name.to_string(),
+            content: content.to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        facts.push(fact.clone());
+        Ok(fact)
+    }
+
+    async fn recall(
+        &self,
+        agent_name: &str,
+        query: &str,
+        limit: usize
+    ) -> Result<Vec<MemoryFact>, MemoryError> {
+        let facts = self.facts
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|fact| fact.agent_name == agent_name)
+            .cloned()
+            .collect();
+        Ok(rank_facts(facts, query, limit))
+    }
+
+    async fn forget(&self, agent_name: &str) -> Result<(), MemoryError> {
+        self.facts
+            .write()
+            .unwrap()
+            .retain(|fact| fact.agent_name != agent_name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ids_are_not_reused_after_forget() {
+        let store = InMemoryStore::new();
+        let first = store.remember("agent", "The user lives in Lisbon").await.unwrap();
+        store.remember("other", "The user likes tea").await.unwrap();
+        store.forget("agent").await.unwrap();
+        let second = store.remember("agent", "The user works remotely").await.unwrap();
+        assert!(second.id > first.id);
+        assert_ne!(second.id, 2);
+    }
+
+    #[tokio::test]
+    async fn duplicate_facts_are_stored_once() {
+        let store = InMemoryStore::new();
+        let first = store.remember("agent", "The user lives in Lisbon").await.unwrap();
+        let second = store.remember("agent", "The user lives in Lisbon").await.unwrap();
+        assert_eq!(first.id, second.id);
+        let facts = store.recall("agent", "where does the user live Lisbon", 10).await.unwrap();
+        assert_eq!(facts.len(), 1);
+    }
+}
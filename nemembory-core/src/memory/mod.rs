@@ -0,0 +1,8 @@
+pub mod store;
+pub mod in_memory;
+pub mod file;
+pub mod postgres;
+pub use store::{ MemoryStore, MemoryFact, MemoryError, extract_facts, format_memories };
+pub use in_memory::InMemoryStore;
+pub use file::FileMemoryStore;
+pub use postgres::DbMemoryStore;
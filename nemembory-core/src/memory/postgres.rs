@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::memory::store::{ MemoryError, MemoryFact, MemoryStore };
+
+/// Memory store backed by the `memories` table, ranked with Postgres full text search
+pub struct DbMemoryStore {
+    pub db_pool: PgPool,
+}
+
+impl DbMemoryStore {
+    pub async fn new(conn_string: &str) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            db_pool: PgPool::connect(conn_string).await?,
+        })
+    }
+
+    pub fn from_pool(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[async_trait]
+impl MemoryStore for DbMemoryStore {
+    async fn remember(&self, agent_name: &str, content: &str) -> Result<MemoryFact, MemoryError> {
+        // The no-op update makes RETURNING yield the stored row when the fact already exists
+        let query =
+            r#"INSERT INTO memories (agent_name, content) VALUES ($1, $2)
+            ON CONFLICT (agent_name, md5(content)) DO UPDATE SET content = EXCLUDED.content
+            RETURNING id, agent_name, content, created_at"#;
+        let fact = sqlx::query_as::<_, MemoryFact>(query)
+            .bind(agent_name)
+            .bind(content)
+            .fetch_one(&self.db_pool).await?;
+        Ok(fact)
+    }
+
+    async fn recall(
+        &self,
+        agent_name: &str,
+        query: &str,
+        limit: usize
+    ) -> Result<Vec<MemoryFact>, MemoryError> {
+        let sql =
+            r#"WITH q AS (
+                SELECT to_tsquery('english', replace(plainto_tsquery('english', $2)::text, '&', '|')) AS query
+            )
+            SELECT id, agent_name, content, created_at FROM memories, q
+            WHERE agent_name = $1 AND to_tsvector('english', content) @@ q.query
+            ORDER BY ts_rank(to_tsvector('english', content), q.query) DESC, created_at DESC
+            LIMIT $3"#;
+        let facts = sqlx::query_as::<_, MemoryFact>(sql)
+            .bind(agent_name)
+            .bind(query)
+            .bind(limit as i64)
+            .fetch_all(&self.db_pool).await?;
+        Ok(facts)
+    }
+
+    async fn forget(&self, agent_name: &str) -> Result<(), MemoryError> {
+        sqlx::query("DELETE FROM memories WHERE agent_name = $1")
+            .bind(agent_name)
+            .execute(&self.db_pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::test_pool;
+
+    #[tokio::test]
+    async fn facts_are_stored_once_and_recalled_by_relevance() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let store = DbMemoryStore::from_pool(pool);
+        let agent = format!("memory-test-{}", std::process::id());
+        store.forget(&agent).await.unwrap();
+
+        let first = store.remember(&agent, "The user lives in Lisbon").await.unwrap();
+        let second = store.remember(&agent, "The user lives in Lisbon").await.unwrap();
+        assert_eq!(first.id, second.id);
+        store.remember(&agent, "The user likes green tea").await.unwrap();
+
+        let facts = store.recall(&agent, "Which city does the user live in?", 5).await.unwrap();
+        assert_eq!(facts[0].content, "The user lives in Lisbon");
+
+        store.forget(&agent).await.unwrap();
+        assert!(store.recall(&agent, "tea", 5).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use chrono::{ DateTime, Utc };
+use serde::{ Deserialize, Serialize };
+use sqlx::prelude::FromRow;
+
+/// A single fact remembered by an agent across conversations
+#[derive(Clone, Serialize, Deserialize, FromRow, Debug)]
+pub struct MemoryFact {
+    pub id: i64,
+    pub agent_name: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryError {
+    #[error("Memory io error: {0}")] IoError(String),
+    #[error("Memory serialization error: {0}")] SerializationError(String),
+    #[error("Memory sql error: {0}")] SqlError(String),
+}
+
+impl From<std::io::Error> for MemoryError {
+    fn from(err: std::io::Error) -> Self {
+        MemoryError::IoError(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for MemoryError {
+    fn from(err: serde_json::Error) -> Self {
+        MemoryError::SerializationError(err.to_string())
+    }
+}
+
+impl From<sqlx::Error> for MemoryError {
+    fn from(err: sqlx::Error) -> Self {
+        MemoryError::SqlError(err.to_string())
+    }
+}
+
+/// Long-term memory that outlives a single `NememboryAgent` session
+#[async_trait]
+pub trait MemoryStore: Send + Sync {
+    /// Persists a fact for the given agent. Remembering content the agent already has
+    /// returns the existing fact instead of storing a duplicate.
+    async fn remember(&self, agent_name: &str, content: &str) -> Result<MemoryFact, MemoryError>;
+
+    /// Returns up to `limit` facts for the given agent, most relevant to `query` first
+    async fn recall(
+        &self,
+        agent_name: &str,
+        query: &str,
+        limit: usize
+    ) -> Result<Vec<MemoryFact>, MemoryError>;
+
+    /// Removes every fact stored for the given agent
+    async fn forget(&self, agent_name: &str) -> Result<(), MemoryError>;
+}
+
+fn keywords(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() > 2)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Scores a fact against a query by counting the query keywords it contains
+pub fn relevance(query: &str, content: &str) -> usize {
+    let content_words = keywords(content);
+    keywords(query)
+        .iter()
+        .filter(|word| content_words.contains(word))
+        .count()
+}
+
+/// Ranks facts by relevance to `query`, dropping facts that share no keywords with it
+pub fn rank_facts(facts: Vec<MemoryFact>, query: &str, limit: usize) -> Vec<MemoryFact> {
+    let mut scored: Vec<(usize, MemoryFact)> = facts
+        .into_iter()
+        .map(|fact| (relevance(query, &fact.content), fact))
+        .filter(|(score, _)| *score > 0)
+        .collect();
+
+    // Highest score first, newer facts win ties
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        b_score.cmp(a_score).then(b.created_at.cmp(&a.created_at))
+    });
+
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, fact)| fact)
+        .collect()
+}
+
+/// Extracts the facts worth remembering from a completed exchange.
+///
+/// Declarative sentences from the user are kept verbatim, and the exchange itself
+/// is condensed into a single question/answer fact.
+pub fn extract_facts(prompt: &str, response: &str) -> Vec<String> {
+    let mut facts: Vec<String> = prompt
+        .split_inclusive(['.', '!', '?', '\n'])
+        .map(|sentence| sentence.trim())
+        .filter(|sentence| !sentence.is_empty() && !sentence.ends_with('?'))
+        .filter(|sentence| keywords(sentence).len() >= 3)
+        .map(|sentence| sentence.to_string())
+        .collect();
+
+    let answer: String = response.chars().take(500).collect();
+    if !prompt.trim().is_empty() && !answer.trim().is_empty() {
+        facts.push(format!("User asked: {}\nAssistant answered: {}", prompt.trim(), answer.trim()));
+    }
+
+    // A prompt repeating a sentence would otherwise store it twice
+    let mut seen = std::collections::HashSet::new();
+    facts.retain(|fact| seen.insert(fact.clone()));
+    facts
+}
+
+/// Formats recalled facts into a block that is prepended to the user prompt
+pub fn format_memories(facts: &[MemoryFact], prompt: &str) -> String {
+    if facts.is_empty() {
+        return prompt.to_string();
+    }
+
+    let memories = facts
+        .iter()
+        .map(|fact| format!("- {}", fact.content))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    format!(
+        "# Relevant memories from previous conversations:\n{}\n\n# User request:\n{}",
+        memories,
+        prompt
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_facts_keeps_statements_and_condenses_the_exchange() {
+        let prompt = "I live in Lisbon with my family. I work as a night nurse! Where can I eat?";
+        let answer = "Try the seafood places in Alfama.";
+        let facts = extract_facts(prompt, answer);
+        assert_eq!(facts, [
+            "I live in Lisbon with my family.".to_string(),
+            "I work as a night nurse!".to_string(),
+            format!("User asked: {}\nAssistant answered: {}", prompt, answer),
+        ]);
+    }
+
+    #[test]
+    fn extract_facts_skips_short_and_repeated_sentences() {
+        let facts = extract_facts("Hi. My cat is called Tom. My cat is called Tom.", "");
+        assert_eq!(facts, ["My cat is called Tom."]);
+    }
+
+    #[test]
+    fn format_memories_prepends_the_facts() {
+        let fact = MemoryFact {
+            id: 1,
+            agent_name: "agent".to_string(),
+            content: "The user lives in Lisbon".to_string(),
+            created_at: Utc::now(),
+        };
+        assert_eq!(format_memories(&[], "Hello"), "Hello");
+        assert_eq!(
+            format_memories(&[fact], "Hello"),
+            concat!(
+                "# Relevant memories from previous conversations:\n",
+                "- The user lives in Lisbon\n\n# User request:\nHello"
+            )
+        );
+    }
+}
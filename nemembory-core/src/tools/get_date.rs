@@ -7,7 +7,7 @@ use serde_json::json;
 #[error("Date retrieval error")]
 pub struct DateError;
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct GetDate;
 
 impl Tool for GetDate {
@@ -6,12 +6,40 @@ use reqwest;
 use html2md;
 use tracing::instrument;
 
+use crate::tools::network::{ NetworkError, NetworkPolicy };
+use crate::tools::rest_api::DEFAULT_REQUEST_TIMEOUT;
+
 #[derive(Debug, Error)]
-#[error("Failed to fetch or convert link contents")]
-pub struct LinkToMarkdownError;
+pub enum LinkToMarkdownError {
+    #[error("Failed to fetch link contents: {0}")] Fetch(String),
+    #[error(transparent)] Network(#[from] NetworkError),
+}
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct LinkToMarkdown;
+impl From<reqwest::Error> for LinkToMarkdownError {
+    fn from(error: reqwest::Error) -> Self {
+        match NetworkError::find(&error) {
+            Some(error) => LinkToMarkdownError::Network(error),
+            None => LinkToMarkdownError::Fetch(error.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct LinkToMarkdown {
+    /// Hosts and addresses the model may fetch, see `NetworkPolicy`
+    pub network: NetworkPolicy,
+}
+
+impl LinkToMarkdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_network_policy(mut self, network: NetworkPolicy) -> Self {
+        self.network = network;
+        self
+    }
+}
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct LinkToMarkdownArgs {
@@ -43,10 +71,15 @@ impl Tool for LinkToMarkdown {
 
     #[instrument(ret, err)]
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let resp = reqwest::get(&args.url).await.map_err(|e: reqwest::Error| LinkToMarkdownError)?;
-        let html = resp.text().await.map_err(|_| LinkToMarkdownError)?;
+        let url = reqwest::Url::parse(&args.url).map_err(|e|
+            NetworkError::InvalidUrl(format!("{}: {}", args.url, e))
+        )?;
+        self.network.check_url(&url)?;
+
+        let client = self.network.client(DEFAULT_REQUEST_TIMEOUT)?;
+        let resp = client.get(url).send().await?.error_for_status()?;
+        let html = self.network.read_body(resp, LinkToMarkdownError::from).await?;
 
-        dbg!("html {:?}", &html);
         let markdown = html2md::parse_html(&html);
         Ok(markdown)
     }
@@ -1,10 +1,37 @@
 pub mod web_search;
 pub mod shell_tool;
 pub use shell_tool::*;
+pub mod sandbox;
+pub use sandbox::ShellSandbox;
+pub mod shell_session;
+pub use shell_session::{ ShellSessionArgs, ShellSessionOutput, ShellSessionTool };
+pub mod credentials;
+pub use credentials::{ Credential, CredentialError, CredentialProfile, Credentials };
+pub mod network;
+pub use network::{ Cidr, NetworkError, NetworkPolicy };
 pub mod rest_api;
 pub use rest_api::*;
+pub mod openapi;
+pub use openapi::{ OpenApiError, OpenApiSpec, OpenApiTool };
 pub mod link_to_markdown;
 pub use link_to_markdown::LinkToMarkdown;
-pub use web_search::WebSearch;
+pub use web_search::{ WebSearch, WebSearchArgs };
+pub mod search_provider;
+pub use search_provider::{
+    BraveProvider,
+    FixtureProvider,
+    Freshness,
+    SearchError,
+    SearchProvider,
+    SearchRequest,
+    SearxngProvider,
+    WebSearchResult,
+};
 pub mod get_date;
 pub use get_date::GetDate;
+pub mod registry;
+pub use registry::{ ToolFactory, ToolRegistry, UnknownToolsError };
+pub mod policy;
+pub use policy::PolicyTool;
+pub mod timeout;
+pub use timeout::TimeoutTool;
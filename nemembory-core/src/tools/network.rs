@@ -0,0 +1,451 @@
+use reqwest::dns::{ Addrs, Name, Resolve, Resolving };
+use serde::{ Deserialize, Serialize };
+use std::error::Error;
+use std::fmt;
+use std::net::{ IpAddr, SocketAddr };
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::agent::policy::host_matches;
+
+/// Loopback, private, link-local (cloud metadata), shared, unspecified, IETF protocol,
+/// benchmarking, documentation, multicast and broadcast ranges, plus NAT64 and
+/// IPv4-compatible IPv6, which map onto any IPv4 address
+pub const DEFAULT_DENY_CIDRS: [&str; 18] = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "255.255.255.255/32",
+    "::/96",
+    "::1/128",
+    "64:ff9b::/96",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+pub const DEFAULT_DENY_HOSTS: [&str; 3] = ["localhost", "*.localhost", "metadata.google.internal"];
+
+/// Errors start with "Blocked by the network policy" so the model can tell them apart from
+/// failing endpoints
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum NetworkError {
+    #[error("Blocked by the network policy: scheme {0} is not allowed")] BlockedScheme(String),
+    #[error("Blocked by the network policy: host {0} is not allowed")] BlockedHost(String),
+    #[error("Blocked by the network policy: {host} resolves to denied address {addr}")]
+    BlockedAddress {
+        host: String,
+        addr: IpAddr,
+    },
+    #[error("Blocked by the network policy: more than {0} redirects")] TooManyRedirects(usize),
+    #[error("Blocked redirect: {0}")] BlockedRedirect(String),
+    #[error("Blocked by the network policy: response over {0} bytes")] ResponseTooLarge(usize),
+    #[error("Invalid url: {0}")] InvalidUrl(String),
+    #[error("Invalid CIDR: {0}")] InvalidCidr(String),
+    #[error("Failed to resolve {0}")] Resolve(String),
+}
+
+impl NetworkError {
+    /// The policy error behind a failed request, e.g. a blocked redirect or address
+    pub fn find(error: &reqwest::Error) -> Option<NetworkError> {
+        let mut source = error.source();
+        while let Some(error) = source {
+            if let Some(error) = error.downcast_ref::<NetworkError>() {
+                return Some(error.clone());
+            }
+            source = error.source();
+        }
+        None
+    }
+}
+
+/// An IP range such as `10.0.0.0/8`; a bare address is a range of one
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical_ip(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - (self.prefix as u32)).unwrap_or(0);
+                (u32::from(network) & mask) == (u32::from(ip) & mask)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - (self.prefix as u32)).unwrap_or(0);
+                (u128::from(network) & mask) == (u128::from(ip) & mask)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The address of an IP host; IPv6 hosts keep their brackets in urls
+fn parse_ip(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// IPv4-mapped IPv6 addresses (`::ffff:127.0.0.1`) are checked as IPv4
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || NetworkError::InvalidCidr(s.to_string());
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = canonical_ip(addr.trim().parse::<IpAddr>().map_err(|_| invalid())?);
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().map_err(|_| invalid())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = NetworkError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Where `RestApiTool` and `LinkToMarkdown` may connect. The default allows http and https
+/// to public addresses only, so the model cannot reach localhost, cloud metadata endpoints
+/// or the private network. Every redirect hop and every resolved address is checked.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NetworkPolicy {
+    pub schemes: Vec<String>,
+    /// When not empty, only these hosts (`api.example.com` or `*.example.com`) are allowed
+    pub allow_hosts: Vec<String>,
+    pub deny_hosts: Vec<String>,
+    /// Addresses allowed even when a deny range contains them
+    pub allow_cidrs: Vec<Cidr>,
+    pub deny_cidrs: Vec<Cidr>,
+    pub max_redirects: usize,
+    pub max_response_bytes: usize,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        Self {
+            schemes: vec!["http".to_string(), "https".to_string()],
+            allow_hosts: Vec::new(),
+            deny_hosts: DEFAULT_DENY_HOSTS.map(String::from).to_vec(),
+            allow_cidrs: Vec::new(),
+            deny_cidrs: DEFAULT_DENY_CIDRS.iter()
+                .map(|cidr| cidr.parse().expect("default CIDRs are valid"))
+                .collect(),
+            max_redirects: 5,
+            max_response_bytes: 5 * 1024 * 1024,
+        }
+    }
+}
+
+impl NetworkPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows every host and address, keeping the scheme, redirect and size limits
+    pub fn unrestricted() -> Self {
+        Self { deny_hosts: Vec::new(), deny_cidrs: Vec::new(), ..Self::default() }
+    }
+
+    pub fn allow_scheme(mut self, scheme: &str) -> Self {
+        self.schemes.push(scheme.to_lowercase());
+        self
+    }
+
+    pub fn allow_host(mut self, pattern: &str) -> Self {
+        self.allow_hosts.push(pattern.to_lowercase());
+        self
+    }
+
+    pub fn deny_host(mut self, pattern: &str) -> Self {
+        self.deny_hosts.push(pattern.to_lowercase());
+        self
+    }
+
+    /// Allows a range the deny list blocks, e.g. `"10.1.0.0/16".parse()?` for an internal API
+    pub fn allow_cidr(mut self, cidr: Cidr) -> Self {
+        self.allow_cidrs.push(cidr);
+        self
+    }
+
+    pub fn deny_cidr(mut self, cidr: Cidr) -> Self {
+        self.deny_cidrs.push(cidr);
+        self
+    }
+
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn with_max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Checks the scheme and host of `url`, and its address when the host is an IP. An IP
+    /// host listed in `deny_hosts` is blocked; with `allow_hosts` set, it must be listed
+    /// there or covered by `allow_cidrs`.
+    pub fn check_url(&self, url: &reqwest::Url) -> Result<(), NetworkError> {
+        if !self.schemes.iter().any(|scheme| scheme.eq_ignore_ascii_case(url.scheme())) {
+            return Err(NetworkError::BlockedScheme(url.scheme().to_string()));
+        }
+        let Some(host) = url.host_str() else {
+            return Err(NetworkError::InvalidUrl(format!("{} has no host", url)));
+        };
+        if let Some(ip) = parse_ip(host) {
+            let listed = |patterns: &[String]| {
+                patterns
+                    .iter()
+                    .filter_map(|pattern| parse_ip(pattern))
+                    .any(|listed| canonical_ip(listed) == canonical_ip(ip))
+            };
+            let in_range = self.allow_cidrs.iter().any(|cidr| cidr.contains(ip));
+            let allowed = self.allow_hosts.is_empty() || listed(&self.allow_hosts) || in_range;
+            if listed(&self.deny_hosts) || !allowed {
+                return Err(NetworkError::BlockedHost(host.to_string()));
+            }
+            return self.check_ip(host, ip);
+        }
+
+        let host = host.to_lowercase();
+        let host = host.trim_end_matches('.');
+        let matches = |patterns: &[String]| {
+            patterns.iter().any(|pattern| host_matches(pattern, host))
+        };
+        let allowed = self.allow_hosts.is_empty() || matches(&self.allow_hosts);
+        if matches(&self.deny_hosts) || !allowed {
+            return Err(NetworkError::BlockedHost(host.to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_ip(&self, host: &str, addr: IpAddr) -> Result<(), NetworkError> {
+        if self.allow_cidrs.iter().any(|cidr| cidr.contains(addr)) {
+            return Ok(());
+        }
+        if self.deny_cidrs.iter().any(|cidr| cidr.contains(addr)) {
+            return Err(NetworkError::BlockedAddress { host: host.to_string(), addr });
+        }
+        Ok(())
+    }
+
+    /// A client that enforces the policy on every redirect and on every address it
+    /// connects to, which also covers hosts resolving to private addresses
+    pub fn client(&self, timeout: Duration) -> Result<reqwest::Client, NetworkError> {
+        self.client_with_redirect_check(timeout, |_| Ok(()))
+    }
+
+    /// Like `client`, with `check` also run on every redirect target, e.g. to keep a
+    /// credential from following a redirect to another host
+    pub fn client_with_redirect_check<F>(
+        &self,
+        timeout: Duration,
+        check: F
+    ) -> Result<reqwest::Client, NetworkError>
+        where F: Fn(&reqwest::Url) -> Result<(), NetworkError> + Send + Sync + 'static
+    {
+        let policy = self.clone();
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > policy.max_redirects {
+                let error = NetworkError::TooManyRedirects(policy.max_redirects);
+                return attempt.error(error);
+            }
+            match policy.check_url(attempt.url()).and_then(|()| check(attempt.url())) {
+                Ok(()) => attempt.follow(),
+                Err(error) => attempt.error(error),
+            }
+        });
+        reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(redirect)
+            .dns_resolver(Arc::new(PolicyResolver { policy: self.clone() }))
+            // A proxy would resolve the host itself, out of the policy's reach
+            .no_proxy()
+            .build()
+            .map_err(|e| NetworkError::InvalidUrl(e.to_string()))
+    }
+
+    /// Reads the body, failing once it grows past `max_response_bytes`
+    pub async fn read_body<E, F>(
+        &self,
+        mut response: reqwest::Response,
+        request_error: F
+    ) -> Result<String, E>
+        where E: From<NetworkError>, F: Fn(reqwest::Error) -> E
+    {
+        let limit = self.max_response_bytes;
+        if response.content_length().is_some_and(|length| (length as usize) > limit) {
+            return Err(NetworkError::ResponseTooLarge(limit).into());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(&request_error)? {
+            if body.len() + chunk.len() > limit {
+                return Err(NetworkError::ResponseTooLarge(limit).into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(String::from_utf8_lossy(&body).to_string())
+    }
+}
+
+/// Resolves host names and rejects them when any of their addresses is denied
+struct PolicyResolver {
+    policy: NetworkPolicy,
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs = tokio::net::lookup_host((host.as_str(), 0)).await
+                .map_err(|e| NetworkError::Resolve(format!("{}: {}", host, e)))?
+                .collect::<Vec<SocketAddr>>();
+            for addr in &addrs {
+                policy.check_ip(&host, addr.ip())?;
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(policy: &NetworkPolicy, url: &str) -> Result<(), NetworkError> {
+        policy.check_url(&reqwest::Url::parse(url).unwrap())
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn cidrs_parse_and_match() {
+        let private: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(private.contains(ip("10.20.30.40")));
+        assert!(!private.contains(ip("11.0.0.1")));
+        // IPv4-mapped IPv6 addresses are matched as IPv4
+        assert!(private.contains(ip("::ffff:10.0.0.1")));
+        assert_eq!("192.168.1.1".parse::<Cidr>().unwrap().to_string(), "192.168.1.1/32");
+        assert!("fe80::/10".parse::<Cidr>().unwrap().contains(ip("fe80::1")));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("example.com/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn default_policy_blocks_internal_addresses() {
+        let policy = NetworkPolicy::new();
+        for url in [
+            "http://127.0.0.1/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://[::7f00:1]/",
+            "http://[64:ff9b::7f00:1]/",
+            "http://192.0.0.170/",
+            "http://[2001:db8::1]/",
+            "http://198.18.0.1/",
+            "http://224.0.0.1/",
+            "http://255.255.255.255/",
+            "http://[ff02::1]/",
+        ] {
+            assert!(
+                matches!(check(&policy, url), Err(NetworkError::BlockedAddress { .. })),
+                "{} is blocked",
+                url
+            );
+        }
+        assert!(check(&policy, "https://93.184.216.34/").is_ok());
+    }
+
+    #[test]
+    fn hosts_and_schemes_are_checked() {
+        let policy = NetworkPolicy::new();
+        assert!(matches!(check(&policy, "ftp://example.com"), Err(NetworkError::BlockedScheme(_))));
+        let local = check(&policy, "http://api.localhost./");
+        assert!(matches!(local, Err(NetworkError::BlockedHost(_))));
+        assert!(check(&policy, "https://Example.com/path").is_ok());
+
+        let policy = NetworkPolicy::new()
+            .allow_host("*.example.com")
+            .deny_host("admin.example.com");
+        assert!(check(&policy, "https://api.example.com").is_ok());
+        assert!(check(&policy, "https://admin.example.com").is_err());
+        assert!(check(&policy, "https://example.org").is_err());
+    }
+
+    #[test]
+    fn denied_hosts_cover_ip_literals() {
+        let policy = NetworkPolicy::new().deny_host("93.184.216.34").deny_host("2606:4700::1");
+        let denied = check(&policy, "http://93.184.216.34/");
+        assert!(matches!(denied, Err(NetworkError::BlockedHost(_))));
+        let denied = check(&policy, "http://[2606:4700:0::1]/");
+        assert!(matches!(denied, Err(NetworkError::BlockedHost(_))));
+        assert!(check(&policy, "http://93.184.216.35/").is_ok());
+    }
+
+    #[test]
+    fn allow_hosts_admit_only_listed_or_allowed_addresses() {
+        let policy = NetworkPolicy::new().allow_host("api.example.com");
+        let literal = check(&policy, "http://93.184.216.34/");
+        assert!(matches!(literal, Err(NetworkError::BlockedHost(_))));
+
+        let policy = NetworkPolicy::new()
+            .allow_host("api.example.com")
+            .allow_host("93.184.216.34");
+        assert!(check(&policy, "http://93.184.216.34/").is_ok());
+        assert!(check(&policy, "http://93.184.216.35/").is_err());
+
+        let policy = NetworkPolicy::new()
+            .allow_host("api.example.com")
+            .allow_cidr("10.1.0.0/16".parse().unwrap());
+        assert!(check(&policy, "http://10.1.2.3/").is_ok());
+        assert!(check(&policy, "http://10.2.0.1/").is_err());
+    }
+}
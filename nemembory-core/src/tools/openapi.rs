@@ -0,0 +1,706 @@
+use rig::{ completion::ToolDefinition, tool::{ ToolDyn, ToolError } };
+use serde_json::{ Map, Value, json };
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::tools::network::{ NetworkError, NetworkPolicy };
+use crate::tools::rest_api::{ DEFAULT_REQUEST_TIMEOUT, RestApiArgs, RestApiError, RestApiTool };
+
+/// Methods that can carry an operation in a path item
+const METHODS: [&str; 7] = ["get", "put", "post", "delete", "patch", "head", "options"];
+
+/// `$ref`s nested deeper than this are replaced with an empty schema, which also stops
+/// recursive schemas
+const MAX_REF_DEPTH: usize = 8;
+
+static NULL: Value = Value::Null;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OpenApiError {
+    #[error("Failed to load the OpenAPI document: {0}")] Load(String),
+    #[error("Invalid OpenAPI document: {0}")] Invalid(String),
+    #[error("The OpenAPI document has no server url, set one with with_base_url")] MissingBaseUrl,
+    #[error("The OpenAPI server url '{0}' is relative, set an absolute one with with_base_url")]
+    RelativeBaseUrl(String),
+    #[error("Operation '{0}' has the name of a registered tool")] NameClash(String),
+    #[error("Missing required parameter: {0}")] MissingParameter(String),
+    #[error(transparent)] Network(#[from] NetworkError),
+    #[error(transparent)] Request(#[from] RestApiError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParameterLocation {
+    Path,
+    Query,
+    Header,
+}
+
+#[derive(Clone, Debug)]
+pub struct OperationParameter {
+    pub name: String,
+    pub location: ParameterLocation,
+    pub required: bool,
+    pub description: Option<String>,
+    pub schema: Value,
+}
+
+/// One operation of the document, e.g. `GET /pets/{petId}`
+#[derive(Clone, Debug)]
+pub struct Operation {
+    /// Tool name, from the `operationId` or the method and path
+    pub name: String,
+    pub description: String,
+    pub method: String,
+    pub path: String,
+    pub parameters: Vec<OperationParameter>,
+    /// JSON schema of the request body and whether it is required
+    pub body: Option<(Value, bool)>,
+}
+
+/// An OpenAPI 3 document (JSON) turned into one tool per operation. Requests go through
+/// `RestApiTool`, so its timeout and credential profiles apply.
+#[derive(Clone, Debug)]
+pub struct OpenApiSpec {
+    pub title: String,
+    pub base_url: String,
+    pub operations: Vec<Operation>,
+    rest: RestApiTool,
+    credential: Option<String>,
+}
+
+impl OpenApiSpec {
+    /// Parses a document. A relative or missing server url must be replaced with
+    /// `with_base_url` before the operations can be called.
+    pub fn from_json(json: &str) -> Result<Self, OpenApiError> {
+        let document: Value = serde_json::from_str(json).map_err(|e|
+            OpenApiError::Load(e.to_string())
+        )?;
+        let version = document["openapi"].as_str().unwrap_or_default();
+        if !version.starts_with('3') {
+            return Err(OpenApiError::Invalid(format!("unsupported version '{}'", version)));
+        }
+
+        Ok(Self {
+            title: document["info"]["title"].as_str().unwrap_or_default().to_string(),
+            base_url: document["servers"][0]["url"].as_str().unwrap_or_default().to_string(),
+            operations: parse_operations(&document)?,
+            rest: RestApiTool::new(),
+            credential: None,
+        })
+    }
+
+    /// Reads a document from disk, see `from_json`
+    pub fn from_file(path: &str) -> Result<Self, OpenApiError> {
+        let json = std::fs::read_to_string(path).map_err(|e|
+            OpenApiError::Load(format!("{}: {}", path, e))
+        )?;
+        Self::from_json(&json)
+    }
+
+    /// Downloads the document under the default `NetworkPolicy`. A relative server url,
+    /// or a missing one, is resolved against `url`.
+    pub async fn from_url(url: &str) -> Result<Self, OpenApiError> {
+        Self::from_url_with_network(url, &NetworkPolicy::new()).await
+    }
+
+    /// Like `from_url`, under `network`, e.g. one allowing a document served on localhost
+    pub async fn from_url_with_network(
+        url: &str,
+        network: &NetworkPolicy
+    ) -> Result<Self, OpenApiError> {
+        let document_url = reqwest::Url::parse(url).map_err(|e|
+            NetworkError::InvalidUrl(format!("{}: {}", url, e))
+        )?;
+        network.check_url(&document_url)?;
+        let load_error = |e: reqwest::Error| {
+            match NetworkError::find(&e) {
+                Some(error) => OpenApiError::Network(error),
+                None => OpenApiError::Load(format!("{}: {}", url, e)),
+            }
+        };
+        let response = network
+            .client(DEFAULT_REQUEST_TIMEOUT)?
+            .get(document_url.clone())
+            .send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(load_error)?;
+        let json = network.read_body(response, load_error).await?;
+        let mut spec = Self::from_json(&json)?;
+
+        let server = if spec.base_url.is_empty() { "/" } else { spec.base_url.as_str() };
+        if let Ok(base_url) = document_url.join(server) {
+            spec.base_url = base_url.to_string();
+        }
+        Ok(spec)
+    }
+
+    /// Fails unless the operations have an absolute url to be sent to
+    pub fn check_base_url(&self) -> Result<(), OpenApiError> {
+        check_base_url(&self.base_url)
+    }
+
+    /// Overrides the server url of the document
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// Sends the requests through `rest`, e.g. one configured with credentials
+    pub fn with_rest_api(mut self, rest: RestApiTool) -> Self {
+        self.rest = rest;
+        self
+    }
+
+    /// Restricts the requests of every operation to `network`
+    pub fn with_network_policy(mut self, network: NetworkPolicy) -> Self {
+        self.rest = self.rest.with_network_policy(network);
+        self
+    }
+
+    /// Authenticates every operation with the named credential profile of the `RestApiTool`
+    pub fn with_credential(mut self, profile: &str) -> Self {
+        self.credential = Some(profile.to_string());
+        self
+    }
+
+    pub fn tools(&self) -> Vec<OpenApiTool> {
+        self.operations
+            .iter()
+            .map(|operation| OpenApiTool {
+                operation: operation.clone(),
+                base_url: self.base_url.clone(),
+                rest: self.rest.clone(),
+                credential: self.credential.clone(),
+            })
+            .collect()
+    }
+}
+
+fn check_base_url(base_url: &str) -> Result<(), OpenApiError> {
+    if base_url.is_empty() {
+        return Err(OpenApiError::MissingBaseUrl);
+    }
+    match reqwest::Url::parse(base_url) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(OpenApiError::RelativeBaseUrl(base_url.to_string())),
+    }
+}
+
+fn parse_operations(document: &Value) -> Result<Vec<Operation>, OpenApiError> {
+    let paths = document["paths"]
+        .as_object()
+        .ok_or_else(|| OpenApiError::Invalid("missing paths".to_string()))?;
+
+    let mut operations = Vec::new();
+    let mut names = HashSet::new();
+    for (path, item) in paths {
+        let item = resolve(document, item);
+        let shared_parameters = item["parameters"].as_array().cloned().unwrap_or_default();
+        for method in METHODS {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+
+            // Operation parameters override path item parameters with the same name and location
+            let mut parameters: Vec<OperationParameter> = Vec::new();
+            let declared = shared_parameters
+                .iter()
+                .chain(operation["parameters"].as_array().into_iter().flatten());
+            for parameter in declared {
+                if let Some(parameter) = parse_parameter(document, parameter) {
+                    parameters.retain(|p| {
+                        p.name != parameter.name || p.location != parameter.location
+                    });
+                    parameters.push(parameter);
+                }
+            }
+
+            let body = resolve(document, &operation["requestBody"]);
+            let body = body["content"]["application/json"]["schema"]
+                .as_object()
+                .map(|schema| {
+                    let schema = inline_refs(document, &Value::Object(schema.clone()), 0);
+                    (schema, body["required"].as_bool().unwrap_or(false))
+                });
+
+            let description = [&operation["summary"], &operation["description"]]
+                .iter()
+                .filter_map(|text| text.as_str())
+                .collect::<Vec<&str>>()
+                .join(" ");
+            let description = if description.is_empty() {
+                format!("{} {}", method.to_uppercase(), path)
+            } else {
+                format!("{} ({} {})", description, method.to_uppercase(), path)
+            };
+
+            // Ids without a usable character fall back to the method and path
+            let name = match operation["operationId"].as_str().map(tool_name) {
+                Some(name) if !name.is_empty() => name,
+                _ => tool_name(&format!("{}_{}", method, path)),
+            };
+            let name = unique_name(&name, &mut names);
+            operations.push(Operation {
+                name,
+                description,
+                method: method.to_uppercase(),
+                path: path.clone(),
+                parameters,
+                body,
+            });
+        }
+    }
+    Ok(operations)
+}
+
+fn parse_parameter(document: &Value, parameter: &Value) -> Option<OperationParameter> {
+    let parameter = resolve(document, parameter);
+    let location = match parameter["in"].as_str()? {
+        "path" => ParameterLocation::Path,
+        "query" => ParameterLocation::Query,
+        "header" => ParameterLocation::Header,
+        // Cookie parameters are not supported
+        _ => {
+            return None;
+        }
+    };
+    let schema = match parameter.get("schema") {
+        Some(schema) => inline_refs(document, schema, 0),
+        None => json!({ "type": "string" }),
+    };
+    Some(OperationParameter {
+        name: parameter["name"].as_str()?.to_string(),
+        location,
+        required: location == ParameterLocation::Path || parameter["required"] == true,
+        description: parameter["description"].as_str().map(String::from),
+        schema,
+    })
+}
+
+/// Follows a local `$ref` such as `#/components/parameters/limit`
+fn resolve<'a>(document: &'a Value, value: &'a Value) -> &'a Value {
+    match value["$ref"].as_str().and_then(|r| r.strip_prefix('#')) {
+        Some(pointer) => document.pointer(pointer).unwrap_or(&NULL),
+        None => value,
+    }
+}
+
+/// Replaces the `$ref`s of a schema with the schemas they point to
+fn inline_refs(document: &Value, schema: &Value, depth: usize) -> Value {
+    if depth > MAX_REF_DEPTH {
+        return json!({});
+    }
+    match schema {
+        Value::Object(fields) if fields.contains_key("$ref") => {
+            inline_refs(document, resolve(document, schema), depth + 1)
+        }
+        Value::Object(fields) => {
+            Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.clone(), inline_refs(document, value, depth)))
+                    .collect()
+            )
+        }
+        Value::Array(items) => {
+            Value::Array(
+                items
+                    .iter()
+                    .map(|item| inline_refs(document, item, depth))
+                    .collect()
+            )
+        }
+        other => other.clone(),
+    }
+}
+
+/// Tool names may only contain letters, digits, `_` and `-`, up to 64 characters
+fn tool_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect::<String>();
+    name.trim_matches('_').chars().take(64).collect()
+}
+
+/// `name`, or `name_2`, `name_3`... when an earlier operation already took it
+fn unique_name(name: &str, names: &mut HashSet<String>) -> String {
+    let mut unique = name.to_string();
+    let mut count = 1;
+    while names.contains(&unique) {
+        count += 1;
+        let suffix = format!("_{}", count);
+        let prefix: String = name.chars().take(64 - suffix.len()).collect();
+        unique = format!("{}{}", prefix, suffix);
+    }
+    names.insert(unique.clone());
+    unique
+}
+
+/// Calls one operation of an `OpenApiSpec`
+#[derive(Clone, Debug)]
+pub struct OpenApiTool {
+    pub operation: Operation,
+    base_url: String,
+    rest: RestApiTool,
+    credential: Option<String>,
+}
+
+impl OpenApiTool {
+    /// JSON schema of the arguments: one property per parameter plus `body`
+    fn parameters(&self) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for parameter in &self.operation.parameters {
+            let mut schema = parameter.schema.clone();
+            let description = parameter.description.as_ref();
+            if let (Some(fields), Some(description)) = (schema.as_object_mut(), description) {
+                fields.insert("description".to_string(), Value::String(description.clone()));
+            }
+            properties.insert(parameter.name.clone(), schema);
+            if parameter.required {
+                required.push(parameter.name.clone());
+            }
+        }
+        if let Some((schema, body_required)) = &self.operation.body {
+            properties.insert("body".to_string(), schema.clone());
+            if *body_required {
+                required.push("body".to_string());
+            }
+        }
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+
+    /// Builds the `RestApiTool` request from the arguments
+    fn request(&self, args: &Value) -> Result<RestApiArgs, OpenApiError> {
+        check_base_url(&self.base_url)?;
+        let mut path = self.operation.path.clone();
+        let mut request = RestApiArgs {
+            method: Some(self.operation.method.clone()),
+            credential: self.credential.clone(),
+            ..RestApiArgs::default()
+        };
+
+        for parameter in &self.operation.parameters {
+            let value = match args.get(&parameter.name) {
+                Some(value) if !value.is_null() => parameter_value(value),
+                _ if parameter.required => {
+                    return Err(OpenApiError::MissingParameter(parameter.name.clone()));
+                }
+                _ => {
+                    continue;
+                }
+            };
+            match parameter.location {
+                ParameterLocation::Path => {
+                    let placeholder = format!("{{{}}}", parameter.name);
+                    path = path.replace(&placeholder, &encode_path_segment(&value));
+                }
+                ParameterLocation::Query => {
+                    request.query.insert(parameter.name.clone(), value);
+                }
+                ParameterLocation::Header => {
+                    request.headers.insert(parameter.name.clone(), value);
+                }
+            }
+        }
+        match args.get("body") {
+            Some(body) if !body.is_null() => {
+                request.json = Some(body.clone());
+            }
+            _ if matches!(self.operation.body, Some((_, true))) => {
+                return Err(OpenApiError::MissingParameter("body".to_string()));
+            }
+            _ => {}
+        }
+
+        request.url = format!("{}{}", self.base_url.trim_end_matches('/'), path);
+        Ok(request)
+    }
+
+    async fn send(&self, args: Value) -> Result<String, OpenApiError> {
+        let response = self.rest.send(self.request(&args)?).await?;
+        serde_json::to_string(&response).map_err(|e| OpenApiError::Invalid(e.to_string()))
+    }
+}
+
+/// Strings are sent as is, arrays as comma separated values
+fn parameter_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => {
+            items
+                .iter()
+                .map(parameter_value)
+                .collect::<Vec<String>>()
+                .join(",")
+        }
+        other => other.to_string(),
+    }
+}
+
+fn encode_path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{:02X}", byte),
+            }
+        })
+        .collect()
+}
+
+impl ToolDyn for OpenApiTool {
+    fn name(&self) -> String {
+        self.operation.name.clone()
+    }
+
+    fn definition<'a>(
+        &'a self,
+        _prompt: String
+    ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + 'a>> {
+        Box::pin(async move {
+            ToolDefinition {
+                name: self.operation.name.clone(),
+                description: self.operation.description.clone(),
+                parameters: self.parameters(),
+            }
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: String
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + 'a>> {
+        Box::pin(async move {
+            let args: Value = serde_json::from_str(&args).map_err(ToolError::JsonError)?;
+            self.send(args).await.map_err(|e| ToolError::ToolCallError(Box::new(e)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{ Arc, Mutex };
+    use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+    use tokio::net::TcpListener;
+
+    const PETSTORE: &str =
+        r##"{
+        "openapi": "3.0.0",
+        "info": { "title": "Petstore" },
+        "servers": [{ "url": "https://pets.example.com/v1/" }],
+        "paths": {
+            "/pets/{petId}": {
+                "parameters": [{ "$ref": "#/components/parameters/petId" }],
+                "get": {
+                    "operationId": "getPet",
+                    "parameters": [
+                        { "name": "fields", "in": "query", "schema": { "type": "array" } },
+                        { "name": "X-Trace", "in": "header" }
+                    ]
+                },
+                "delete": { "operationId": "getPet" },
+                "put": {
+                    "operationId": "!!!",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": { "schema": { "$ref": "#/components/schemas/Pet" } }
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "parameters": {
+                "petId": { "name": "petId", "in": "path", "schema": { "type": "string" } }
+            },
+            "schemas": {
+                "Pet": { "type": "object", "properties": { "name": { "type": "string" } } }
+            }
+        }
+    }"##;
+
+    fn tool(spec: &OpenApiSpec, method: &str) -> OpenApiTool {
+        spec.tools()
+            .into_iter()
+            .find(|tool| tool.operation.method == method)
+            .unwrap()
+    }
+
+    #[test]
+    fn duplicate_and_empty_operation_ids_get_unique_names() {
+        let spec = OpenApiSpec::from_json(PETSTORE).unwrap();
+        let mut names: Vec<(&str, &str)> = spec.operations
+            .iter()
+            .map(|operation| (operation.method.as_str(), operation.name.as_str()))
+            .collect();
+        names.sort();
+        assert_eq!(names, [("DELETE", "getPet_2"), ("GET", "getPet"), ("PUT", "put__pets__petId")]);
+    }
+
+    #[test]
+    fn unique_names_stay_within_the_length_limit() {
+        let mut names = HashSet::new();
+        let long = "a".repeat(64);
+        assert_eq!(unique_name(&long, &mut names), long);
+        let second = unique_name(&long, &mut names);
+        assert_eq!(second.len(), 64);
+        assert!(second.ends_with("_2"));
+    }
+
+    #[test]
+    fn arguments_fill_the_path_query_and_headers() {
+        let spec = OpenApiSpec::from_json(PETSTORE).unwrap();
+        let args = json!({ "petId": "a b/c", "fields": ["name", "tag"], "X-Trace": 7 });
+
+        let request = tool(&spec, "GET").request(&args).unwrap();
+        assert_eq!(request.method.as_deref(), Some("GET"));
+        assert_eq!(request.url, "https://pets.example.com/v1/pets/a%20b%2Fc");
+        assert_eq!(request.query["fields"], "name,tag");
+        assert_eq!(request.headers["X-Trace"], "7");
+        assert!(request.json.is_none());
+    }
+
+    #[test]
+    fn missing_required_arguments_are_rejected() {
+        let spec = OpenApiSpec::from_json(PETSTORE).unwrap();
+
+        let error = tool(&spec, "GET").request(&json!({})).unwrap_err();
+        assert!(matches!(error, OpenApiError::MissingParameter(name) if name == "petId"));
+        let error = tool(&spec, "PUT").request(&json!({ "petId": "1" })).unwrap_err();
+        assert!(matches!(error, OpenApiError::MissingParameter(name) if name == "body"));
+
+        let body = json!({ "name": "Rex" });
+        let request = tool(&spec, "PUT").request(&json!({ "petId": "1", "body": body })).unwrap();
+        assert_eq!(request.json, Some(body));
+    }
+
+    #[test]
+    fn parameters_and_body_refs_are_inlined() {
+        let spec = OpenApiSpec::from_json(PETSTORE).unwrap();
+
+        let parameters = tool(&spec, "PUT").parameters();
+        assert_eq!(parameters["properties"]["petId"], json!({ "type": "string" }));
+        assert_eq!(parameters["properties"]["body"]["properties"]["name"]["type"], "string");
+        assert_eq!(parameters["required"], json!(["petId", "body"]));
+    }
+
+    #[test]
+    fn relative_server_urls_need_a_base_url() {
+        let relative = PETSTORE.replace("https://pets.example.com/v1/", "/v1");
+        let spec = OpenApiSpec::from_json(&relative).unwrap();
+        let args = json!({ "petId": "1" });
+
+        let error = tool(&spec, "GET").request(&args).unwrap_err();
+        assert!(matches!(error, OpenApiError::RelativeBaseUrl(url) if url == "/v1"));
+        assert!(spec.check_base_url().is_err());
+        let spec = spec.with_base_url("https://pets.example.com/v1");
+        let request = tool(&spec, "GET").request(&args).unwrap();
+        assert_eq!(request.url, "https://pets.example.com/v1/pets/1");
+        let spec = spec.with_base_url("");
+        assert!(matches!(spec.check_base_url(), Err(OpenApiError::MissingBaseUrl)));
+    }
+
+    /// Serves every request with a JSON document echoing its request line and body
+    async fn serve() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 1024];
+                let (head, body) = loop {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break (String::new(), String::new()),
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length = head
+                        .to_lowercase()
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length: ")?.parse().ok())
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break (head.to_string(), body.to_string());
+                    }
+                };
+                recorded.lock().unwrap().push(head.clone());
+                let line = head.lines().next().unwrap_or_default();
+                let echo = json!({ "request": line, "body": body }).to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    echo.len(),
+                    echo
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (base_url, requests)
+    }
+
+    #[tokio::test]
+    async fn operations_are_called_end_to_end() {
+        let (base_url, requests) = serve().await;
+        let spec = OpenApiSpec::from_json(PETSTORE)
+            .unwrap()
+            .with_base_url(&base_url)
+            .with_network_policy(NetworkPolicy::unrestricted());
+
+        let args = json!({ "petId": "7", "fields": ["name"], "X-Trace": "abc" });
+        let output = tool(&spec, "GET").call(args.to_string()).await.unwrap();
+        let response: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"]["request"], "GET /v1/pets/7?fields=name HTTP/1.1");
+
+        let args = json!({ "petId": "7", "body": { "name": "Rex" } });
+        let output = tool(&spec, "PUT").call(args.to_string()).await.unwrap();
+        let response: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(response["body"]["request"], "PUT /v1/pets/7 HTTP/1.1");
+        assert_eq!(response["body"]["body"], r#"{"name":"Rex"}"#);
+
+        let error = tool(&spec, "DELETE").call("{}".to_string()).await.unwrap_err();
+        assert!(error.to_string().contains("petId"), "{}", error);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].to_lowercase().contains("x-trace: abc"));
+        assert!(requests[1].to_lowercase().contains("content-type: application/json"));
+    }
+
+    #[tokio::test]
+    async fn operations_follow_the_spec_network_policy() {
+        let spec = OpenApiSpec::from_json(PETSTORE).unwrap().with_base_url("http://127.0.0.1:9");
+        let args = json!({ "petId": "1" });
+
+        let error = tool(&spec, "GET").send(args.clone()).await.unwrap_err();
+        assert!(matches!(error, OpenApiError::Request(RestApiError::Network(_))));
+        let spec = spec.with_network_policy(NetworkPolicy::unrestricted());
+        let error = tool(&spec, "GET").send(args).await.unwrap_err();
+        assert!(!matches!(error, OpenApiError::Request(RestApiError::Network(_))));
+    }
+
+    #[tokio::test]
+    async fn documents_are_fetched_under_the_network_policy() {
+        let error = OpenApiSpec::from_url("http://127.0.0.1:9/openapi.json").await.unwrap_err();
+        assert!(matches!(error, OpenApiError::Network(NetworkError::BlockedAddress { .. })));
+        let error = OpenApiSpec::from_url("file:///etc/openapi.json").await.unwrap_err();
+        assert!(matches!(error, OpenApiError::Network(NetworkError::BlockedScheme(_))));
+    }
+}
@@ -0,0 +1,49 @@
+use rig::{ completion::ToolDefinition, tool::{ ToolDyn, ToolError } };
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::agent::policy::{ PolicyError, ToolDecision, ToolPolicies };
+
+/// Wraps a tool so every call is approved, denied or rewritten by the agent's policies
+/// before it runs. The decision the hooks already made for the call is reused.
+pub struct PolicyTool {
+    inner: Box<dyn ToolDyn>,
+    policies: ToolPolicies,
+}
+
+impl PolicyTool {
+    pub fn new(inner: Box<dyn ToolDyn>, policies: ToolPolicies) -> Self {
+        Self { inner, policies }
+    }
+}
+
+impl ToolDyn for PolicyTool {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn definition<'a>(
+        &'a self,
+        prompt: String
+    ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + 'a>> {
+        self.inner.definition(prompt)
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: String
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + 'a>> {
+        Box::pin(async move {
+            match self.policies.decision(&self.inner.name(), &args).await {
+                ToolDecision::Approve => self.inner.call(args).await,
+                ToolDecision::Rewrite(args) => self.inner.call(args.to_string()).await,
+                ToolDecision::Deny(reason) => {
+                    Err(ToolError::ToolCallError(Box::new(PolicyError::Denied(reason))))
+                }
+                ToolDecision::Abort(reason) => {
+                    Err(ToolError::ToolCallError(Box::new(PolicyError::Aborted(reason))))
+                }
+            }
+        })
+    }
+}
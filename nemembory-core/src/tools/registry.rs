@@ -0,0 +1,238 @@
+use rig::tool::{ Tool, ToolDyn };
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::tools::{
+    GetDate,
+    LinkToMarkdown,
+    OpenApiError,
+    OpenApiSpec,
+    RestApiTool,
+    ShellSessionTool,
+    ShellTool,
+    WebSearch,
+};
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown tools: {}", .0.join(", "))]
+pub struct UnknownToolsError(pub Vec<String>);
+
+/// Creates a fresh boxed tool each time an agent is built
+pub type ToolFactory = Arc<dyn Fn() -> Box<dyn ToolDyn> + Send + Sync>;
+
+/// Maps tool names to factories so agents can be given tools by name at runtime.
+/// Applications register their own tools next to the built-in ones.
+#[derive(Clone)]
+pub struct ToolRegistry {
+    factories: HashMap<String, ToolFactory>,
+    /// HTTP methods of the registered OpenAPI operations, reported to tool policies
+    http_methods: HashMap<String, String>,
+}
+
+impl ToolRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self { factories: HashMap::new(), http_methods: HashMap::new() }
+    }
+
+    /// A registry with `shell_tool`, `shell_session`, `rest_api`, `web_search`,
+    /// `link_to_markdown` and `get_date`
+    pub fn with_builtin_tools() -> Self {
+        let mut registry = Self::new();
+        registry.register_tool(RestApiTool::new());
+        registry.register_tool(WebSearch::new());
+        registry.register_tool(ShellTool::new());
+        // Factory rather than a clone, so every agent gets its own sessions
+        registry.register("shell_session", || Box::new(ShellSessionTool::new()));
+        registry.register_tool(LinkToMarkdown::new());
+        registry.register_tool(GetDate);
+        registry
+    }
+
+    /// Registers a factory under `name`, replacing any tool previously registered with it
+    pub fn register<F>(&mut self, name: &str, factory: F)
+        where F: Fn() -> Box<dyn ToolDyn> + Send + Sync + 'static
+    {
+        self.factories.insert(name.to_string(), Arc::new(factory));
+        self.http_methods.remove(name);
+    }
+
+    /// Registers a tool under its own name; each agent gets a clone of it
+    pub fn register_tool<T>(&mut self, tool: T) where T: Tool + Clone + 'static {
+        let name = tool.name();
+        self.register(&name, move || Box::new(tool.clone()));
+    }
+
+    /// Registers one tool per operation of `spec` and returns their names. Fails without
+    /// registering any when the spec has no absolute base url, or when an operation has the
+    /// name of a tool that is not an OpenAPI operation, e.g. `web_search`.
+    pub fn register_openapi(&mut self, spec: &OpenApiSpec) -> Result<Vec<String>, OpenApiError> {
+        spec.check_base_url()?;
+        let clash = spec.operations
+            .iter()
+            .find(|operation| {
+                self.contains(&operation.name) && self.http_method(&operation.name).is_none()
+            });
+        if let Some(operation) = clash {
+            return Err(OpenApiError::NameClash(operation.name.clone()));
+        }
+        Ok(
+            spec
+                .tools()
+                .into_iter()
+                .map(|tool| {
+                    let name = tool.operation.name.clone();
+                    let method = tool.operation.method.clone();
+                    self.register(&name, move || Box::new(tool.clone()));
+                    self.http_methods.insert(name.clone(), method);
+                    name
+                })
+                .collect()
+        )
+    }
+
+    /// The HTTP method of a registered OpenAPI operation
+    pub fn http_method(&self, name: &str) -> Option<&str> {
+        self.http_methods.get(name).map(String::as_str)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.factories.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Instantiates the named tools. Fails listing every name that is not registered,
+    /// so a typo does not silently leave an agent without a tool.
+    pub fn build(&self, names: &[String]) -> Result<Vec<Box<dyn ToolDyn>>, UnknownToolsError> {
+        let unknown: Vec<String> = names
+            .iter()
+            .filter(|name| !self.contains(name))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(UnknownToolsError(unknown));
+        }
+        Ok(
+            names
+                .iter()
+                .map(|name| self.factories[name]())
+                .collect()
+        )
+    }
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::with_builtin_tools()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str =
+        r#"{
+        "openapi": "3.0.0",
+        "servers": [{ "url": "https://pets.example.com" }],
+        "paths": {
+            "/pets": { "get": { "operationId": "listPets" }, "post": { "operationId": "addPet" } }
+        }
+    }"#;
+
+    fn names(tools: &[Box<dyn ToolDyn>]) -> Vec<String> {
+        tools
+            .iter()
+            .map(|tool| tool.name())
+            .collect()
+    }
+
+    #[test]
+    fn builtin_tools_are_registered() {
+        let registry = ToolRegistry::with_builtin_tools();
+        assert_eq!(registry.names(), [
+            "get_date",
+            "link_to_markdown",
+            "rest_api",
+            "shell_session",
+            "shell_tool",
+            "web_search",
+        ]);
+    }
+
+    #[test]
+    fn build_returns_the_tools_in_order() {
+        let registry = ToolRegistry::with_builtin_tools();
+        let selected = ["get_date".to_string(), "rest_api".to_string()];
+        assert_eq!(names(&registry.build(&selected).unwrap()), selected);
+        assert!(registry.build(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn build_fails_listing_every_unknown_tool() {
+        let registry = ToolRegistry::with_builtin_tools();
+        let selected = ["web_serach".to_string(), "get_date".to_string(), "shell".to_string()];
+        let error = registry.build(&selected).err().unwrap();
+        assert_eq!(error.0, ["web_serach", "shell"]);
+        assert_eq!(error.to_string(), "Unknown tools: web_serach, shell");
+    }
+
+    #[test]
+    fn custom_tools_replace_tools_of_the_same_name() {
+        let mut registry = ToolRegistry::new();
+        registry.register("clock", || Box::new(GetDate));
+        assert!(registry.contains("clock"));
+        // The factory decides the tool, the registered name only selects it
+        assert_eq!(names(&registry.build(&["clock".to_string()]).unwrap()), ["get_date"]);
+
+        registry.register("clock", || Box::new(WebSearch::new()));
+        assert_eq!(names(&registry.build(&["clock".to_string()]).unwrap()), ["web_search"]);
+    }
+
+    #[test]
+    fn openapi_operations_keep_their_http_method() {
+        let mut registry = ToolRegistry::new();
+        let spec = OpenApiSpec::from_json(SPEC).unwrap();
+
+        let mut registered = registry.register_openapi(&spec).unwrap();
+        registered.sort();
+        assert_eq!(registered, ["addPet", "listPets"]);
+        assert_eq!(registry.http_method("listPets"), Some("GET"));
+        assert_eq!(registry.http_method("addPet"), Some("POST"));
+
+        registry.register("addPet", || Box::new(GetDate));
+        assert_eq!(registry.http_method("addPet"), None);
+    }
+
+    #[test]
+    fn openapi_operations_cannot_replace_other_tools() {
+        let mut registry = ToolRegistry::with_builtin_tools();
+        let spec = OpenApiSpec::from_json(SPEC).unwrap();
+        assert_eq!(registry.register_openapi(&spec).unwrap().len(), 2);
+        // Registering a spec again replaces its own operations
+        assert_eq!(registry.register_openapi(&spec).unwrap().len(), 2);
+
+        let clashing = OpenApiSpec::from_json(&SPEC.replace("addPet", "web_search")).unwrap();
+        let error = registry.register_openapi(&clashing).unwrap_err();
+        assert!(matches!(error, OpenApiError::NameClash(name) if name == "web_search"));
+        assert_eq!(registry.http_method("web_search"), None);
+    }
+
+    #[test]
+    fn openapi_specs_need_an_absolute_base_url() {
+        let mut registry = ToolRegistry::new();
+        let relative = SPEC.replace("https://pets.example.com", "/v1");
+        let spec = OpenApiSpec::from_json(&relative).unwrap();
+
+        let error = registry.register_openapi(&spec).unwrap_err();
+        assert!(matches!(error, OpenApiError::RelativeBaseUrl(url) if url == "/v1"));
+        assert!(registry.names().is_empty());
+        let spec = spec.with_base_url("https://pets.example.com/v1");
+        assert!(registry.register_openapi(&spec).is_ok());
+    }
+}
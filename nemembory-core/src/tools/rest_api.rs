@@ -1,35 +1,255 @@
 use serde::{ Deserialize, Serialize };
 use rig::{ tool::Tool, completion::ToolDefinition };
 use anyhow::Result;
+use std::collections::BTreeMap;
+use std::time::Duration;
 
-#[derive(Deserialize, Serialize)]
-pub struct RestApiTool;
+use crate::tools::credentials::{ Credential, CredentialError, Credentials };
+use crate::tools::network::{ NetworkError, NetworkPolicy };
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Default time allowed for a whole request, body included
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Calls HTTP endpoints. The request plumbing (`send`) is shared with the tools generated
+/// from OpenAPI documents.
+#[derive(Clone, Debug)]
+pub struct RestApiTool {
+    pub timeout: Duration,
+    /// Profiles the model can name in `credential`; their secrets never reach the model
+    pub credentials: Credentials,
+    /// Hosts and addresses the model may reach, see `NetworkPolicy`
+    pub network: NetworkPolicy,
+}
+
+impl Default for RestApiTool {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            credentials: Credentials::new(),
+            network: NetworkPolicy::new(),
+        }
+    }
+}
+
+impl RestApiTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    pub fn with_network_policy(mut self, network: NetworkPolicy) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Lists the credential profiles for the tool description
+    fn describe_credentials(&self) -> String {
+        if self.credentials.is_empty() {
+            return String::new();
+        }
+        let profiles = self.credentials
+            .profiles()
+            .iter()
+            .map(|profile| format!("{} ({})", profile.name, profile.hosts.join(", ")))
+            .collect::<Vec<String>>()
+            .join("; ");
+        format!(" Authenticate by naming a credential profile: {}.", profiles)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct RestApiArgs {
     pub url: String,
     #[serde(default)]
     pub method: Option<String>, // GET, POST, etc.
     #[serde(default)]
-    pub body: Option<String>, // For POST/PUT
+    pub headers: BTreeMap<String, String>,
+    /// Query parameters appended to the url
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    /// Raw request body
+    #[serde(default)]
+    pub body: Option<String>,
+    /// JSON request body, sent with `Content-Type: application/json`
+    #[serde(default)]
+    pub json: Option<serde_json::Value>,
+    /// Name of the credential profile to authenticate with
+    #[serde(default)]
+    pub credential: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub response: ResponseOptions,
+}
+
+/// What to return from the response
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResponseOptions {
+    #[serde(default = "default_true")]
+    pub include_headers: bool,
+    /// Body characters kept; the rest is dropped and `truncated` is set
+    #[serde(default)]
+    pub max_body_chars: Option<usize>,
+}
+
+impl Default for ResponseOptions {
+    fn default() -> Self {
+        Self { include_headers: true, max_body_chars: None }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Status, headers and body of a response. Error statuses are returned too, so the model
+/// can read the API's error message.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RestApiResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Parsed JSON when the body is JSON, the text otherwise
+    pub body: serde_json::Value,
+    #[serde(default)]
+    pub truncated: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum RestApiError {
     #[error("Request failed: {0}")] RequestError(String),
+    #[error("Unsupported method: {0}")] UnsupportedMethod(String),
+    #[error("Invalid header {0}")] InvalidHeader(String),
+    #[error("Request timed out after {0:?}")] Timeout(Duration),
+    #[error(transparent)] Credential(#[from] CredentialError),
+    #[error(transparent)] Network(#[from] NetworkError),
     #[error(transparent)] Other(#[from] anyhow::Error),
 }
 
+impl RestApiTool {
+    /// Sends the request described by `args`
+    pub async fn send(&self, args: RestApiArgs) -> Result<RestApiResponse, RestApiError> {
+        let method = args.method.as_deref().unwrap_or("GET").to_uppercase();
+        let method = match method.as_str() {
+            "GET" | "POST" | "PUT" | "PATCH" | "DELETE" | "HEAD" | "OPTIONS" => {
+                reqwest::Method::from_bytes(method.as_bytes()).expect("method is valid")
+            }
+            _ => {
+                return Err(RestApiError::UnsupportedMethod(method));
+            }
+        };
+        let timeout = args.timeout_secs.map(Duration::from_secs).unwrap_or(self.timeout);
+        let url = reqwest::Url::parse(&args.url).map_err(|e|
+            NetworkError::InvalidUrl(format!("{}: {}", args.url, e))
+        )?;
+        self.network.check_url(&url)?;
+
+        let client = match &args.credential {
+            // Custom headers such as `X-Api-Key` survive redirects, so every hop must be a
+            // host the profile may be sent to
+            Some(profile) => {
+                let credentials = self.credentials.clone();
+                let profile = profile.clone();
+                self.network.client_with_redirect_check(timeout, move |url| {
+                    credentials
+                        .resolve(&profile, url.as_str())
+                        .map(|_| ())
+                        .map_err(|e| NetworkError::BlockedRedirect(e.to_string()))
+                })?
+            }
+            None => self.network.client(timeout)?,
+        };
+        let mut request = client.request(method, url).query(&args.query);
+        for (name, value) in &args.headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_|
+                RestApiError::InvalidHeader(name.clone())
+            )?;
+            let value = reqwest::header::HeaderValue::from_str(value).map_err(|_|
+                RestApiError::InvalidHeader(name.to_string())
+            )?;
+            request = request.header(name, value);
+        }
+        if let Some(profile) = &args.credential {
+            request = match self.credentials.resolve(profile, &args.url)? {
+                Credential::Bearer { token } => request.bearer_auth(token),
+                Credential::Basic { username, password } => {
+                    request.basic_auth(username, Some(password))
+                }
+                Credential::ApiKey { header, value } => request.header(header.as_str(), value),
+            };
+        }
+        request = match (args.json, args.body) {
+            (Some(json), _) => request.json(&json),
+            (None, Some(body)) => request.body(body),
+            (None, None) => request,
+        };
+
+        let response = request.send().await.map_err(|e| request_error(e, timeout))?;
+        let status = response.status().as_u16();
+        let headers = if args.response.include_headers {
+            response
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes());
+                    (name.to_string(), self.credentials.redact(&value))
+                })
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        // Endpoints that echo the request must not hand the secret to the model
+        let text = self.network.read_body(response, |e| request_error(e, timeout)).await?;
+        let mut text = self.credentials.redact(&text);
+        let mut truncated = false;
+        if let Some((index, _)) = args.response.max_body_chars.and_then(|max| {
+            text.char_indices().nth(max)
+        }) {
+            text.truncate(index);
+            truncated = true;
+        }
+        let body = match truncated {
+            true => serde_json::Value::String(text),
+            false => serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text)),
+        };
+
+        Ok(RestApiResponse { status, headers, body, truncated })
+    }
+}
+
+fn request_error(error: reqwest::Error, timeout: Duration) -> RestApiError {
+    if let Some(error) = NetworkError::find(&error) {
+        RestApiError::Network(error)
+    } else if error.is_timeout() {
+        RestApiError::Timeout(timeout)
+    } else {
+        RestApiError::RequestError(error.to_string())
+    }
+}
+
 impl Tool for RestApiTool {
     const NAME: &'static str = "rest_api";
     type Error = RestApiError;
     type Args = RestApiArgs;
-    type Output = String;
+    type Output = RestApiResponse;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: "rest_api".to_string(),
-            description: "Calls a RESTful endpoint using reqwest.".to_string(),
+            description: format!(
+                "Calls an HTTP endpoint and returns its status, headers and body.{}",
+                self.describe_credentials()
+            ),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -39,35 +259,246 @@ impl Tool for RestApiTool {
                     },
                     "method": {
                         "type": "string",
-                        "description": "HTTP method (GET, POST, etc.)",
+                        "enum": ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"],
                         "default": "GET"
                     },
+                    "headers": {
+                        "type": "object",
+                        "description": "Request headers, e.g. {\"Accept\": \"application/json\"}",
+                        "additionalProperties": { "type": "string" }
+                    },
+                    "query": {
+                        "type": "object",
+                        "description": "Query parameters appended to the URL",
+                        "additionalProperties": { "type": "string" }
+                    },
+                    "json": {
+                        "description": "JSON request body, sent with Content-Type application/json"
+                    },
                     "body": {
                         "type": "string",
-                        "description": "Request body for POST/PUT",
-                        "default": ""
+                        "description": "Raw request body, ignored when json is set"
+                    },
+                    "credential": {
+                        "type": "string",
+                        "description": "Name of a credential profile that authenticates the request"
+                    },
+                    "timeout_secs": {
+                        "type": "integer",
+                        "description": "Seconds before the request is abandoned"
+                    },
+                    "response": {
+                        "type": "object",
+                        "properties": {
+                            "include_headers": { "type": "boolean", "default": true },
+                            "max_body_chars": {
+                                "type": "integer",
+                                "description": "Truncate the response body to this many characters"
+                            }
+                        }
                     }
                 },
-                "required": ["url", "body","method"]
+                "required": ["url"]
             }),
         }
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let client = reqwest::Client::new();
-        println!("Args: {:?}", &args);
-        let method = args.method.unwrap_or_else(|| "GET".to_string()).to_uppercase();
-        let resp = match method.as_str() {
-            "GET" => client.get(&args.url).send().await,
-            "POST" => client.post(&args.url).body(args.body.unwrap_or_default()).send().await,
-            "PUT" => client.put(&args.url).body(args.body.unwrap_or_default()).send().await,
-            "DELETE" => client.delete(&args.url).send().await,
-            _ => {
-                return Err(RestApiError::RequestError(format!("Unsupported method: {}", method)));
+        self.send(args).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{ Arc, Mutex };
+    use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+    use tokio::net::TcpListener;
+
+    /// Serves `/same` (redirects to `/echo` on the same host), `/other` (redirects to
+    /// `localhost`), `/json` (a JSON document) and `/echo` (returns the request with its head
+    /// lowercased). Records every request the same way.
+    async fn serve() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut head = Vec::new();
+                let mut buffer = [0u8; 1024];
+                while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => head.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let split = head.windows(4).position(|w| w == b"\r\n\r\n").map_or(0, |i| i + 4);
+                let mut body = head.split_off(split);
+                let head = String::from_utf8_lossy(&head).to_lowercase();
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .and_then(|length| length.trim().parse().ok())
+                    .unwrap_or(0);
+                while body.len() < length {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => body.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = format!("{}{}", head, String::from_utf8_lossy(&body));
+                recorded.lock().unwrap().push(request.clone());
+                let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let response = match path.as_str() {
+                    "/same" => redirect(&format!("http://127.0.0.1:{}/echo", port)),
+                    "/other" => redirect(&format!("http://localhost:{}/echo", port)),
+                    "/json" => ok("Content-Type: application/json\r\nX-Custom: yes\r\n", JSON),
+                    _ => ok("", &request),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
             }
+        });
+        (format!("http://127.0.0.1:{}", port), requests)
+    }
+
+    const JSON: &str = r#"{"ok":true,"items":[1,2]}"#;
+
+    fn ok(headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            headers,
+            body.len(),
+            body
+        )
+    }
+
+    fn redirect(location: &str) -> String {
+        format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            location
+        )
+    }
+
+    fn tool() -> RestApiTool {
+        let credential = Credential::ApiKey {
+            header: "X-Api-Key".to_string(),
+            value: "secret-key".to_string(),
         };
-        let response = resp.map_err(|e| RestApiError::RequestError(e.to_string()))?;
-        let text = response.text().await.map_err(|e| RestApiError::RequestError(e.to_string()))?;
-        Ok(text)
+        RestApiTool::new()
+            .with_network_policy(NetworkPolicy::unrestricted())
+            .with_credentials(Credentials::new().with_profile("local", &["127.0.0.1"], credential))
+    }
+
+    fn args(url: String) -> RestApiArgs {
+        RestApiArgs { url, credential: Some("local".to_string()), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn credentials_follow_redirects_to_allowed_hosts_only() {
+        let (base, requests) = serve().await;
+        let tool = tool();
+
+        let response = tool.send(args(format!("{}/same", base))).await.unwrap();
+        assert_eq!(response.status, 200);
+        let error = tool.send(args(format!("{}/other", base))).await.unwrap_err();
+        assert!(matches!(error, RestApiError::Network(NetworkError::BlockedRedirect(_))));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with("get /echo") && requests[1].contains("secret-key"));
+        assert!(!requests.iter().any(|head| head.contains("host: localhost")));
+    }
+
+    #[tokio::test]
+    async fn echoed_secrets_are_redacted() {
+        let (base, _) = serve().await;
+
+        let response = tool().send(args(format!("{}/echo", base))).await.unwrap();
+        let body = response.body.as_str().unwrap();
+        assert!(body.contains("x-api-key: [REDACTED]"));
+        assert!(!body.contains("secret-key"));
+    }
+
+    fn request(method: &str, url: String) -> RestApiArgs {
+        RestApiArgs { url, method: Some(method.to_string()), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn headers_and_query_parameters_are_sent() {
+        let (base, requests) = serve().await;
+        let mut args = request("GET", format!("{}/echo", base));
+        args.headers.insert("X-Trace".to_string(), "abc".to_string());
+        args.query.insert("q".to_string(), "rust lang".to_string());
+        args.query.insert("page".to_string(), "2".to_string());
+
+        tool().send(args).await.unwrap();
+
+        let request = &requests.lock().unwrap()[0];
+        assert!(request.starts_with("get /echo?page=2&q=rust+lang http/1.1"), "{}", request);
+        assert!(request.contains("x-trace: abc\r\n"));
+    }
+
+    #[tokio::test]
+    async fn json_bodies_are_sent_as_json_and_raw_bodies_as_is() {
+        let (base, requests) = serve().await;
+        let mut args = request("post", format!("{}/echo", base));
+        args.json = Some(serde_json::json!({ "name": "Ada" }));
+        args.body = Some("ignored".to_string());
+        tool().send(args).await.unwrap();
+        let mut args = request("PUT", format!("{}/echo", base));
+        args.body = Some("plain text".to_string());
+        tool().send(args).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("post /echo"));
+        assert!(requests[0].contains("content-type: application/json\r\n"));
+        assert!(requests[0].ends_with(r#"{"name":"Ada"}"#));
+        assert!(requests[1].starts_with("put /echo"));
+        assert!(requests[1].ends_with("\r\n\r\nplain text"));
+    }
+
+    #[tokio::test]
+    async fn json_responses_are_parsed_with_their_headers() {
+        let (base, _) = serve().await;
+
+        let response = tool().send(request("GET", format!("{}/json", base))).await.unwrap();
+        assert_eq!(response.body, serde_json::json!({ "ok": true, "items": [1, 2] }));
+        assert_eq!(response.headers["x-custom"], "yes");
+        assert!(!response.truncated);
+
+        let mut args = request("GET", format!("{}/json", base));
+        args.response.include_headers = false;
+        assert!(tool().send(args).await.unwrap().headers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn bodies_are_truncated_to_max_body_chars() {
+        let (base, _) = serve().await;
+        let mut args = request("GET", format!("{}/json", base));
+        args.response.max_body_chars = Some(10);
+
+        let response = tool().send(args).await.unwrap();
+        assert!(response.truncated);
+        assert_eq!(response.body, serde_json::Value::String(JSON[..10].to_string()));
+    }
+
+    #[tokio::test]
+    async fn patch_and_head_are_supported() {
+        let (base, requests) = serve().await;
+
+        let response = tool().send(request("PATCH", format!("{}/echo", base))).await.unwrap();
+        assert_eq!(response.status, 200);
+        let response = tool().send(request("HEAD", format!("{}/json", base))).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, serde_json::Value::String(String::new()));
+
+        let error = tool().send(request("TRACE", format!("{}/echo", base))).await.unwrap_err();
+        assert!(matches!(error, RestApiError::UnsupportedMethod(_)));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("patch /echo"));
+        assert!(requests[1].starts_with("head /json"));
     }
 }
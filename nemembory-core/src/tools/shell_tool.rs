@@ -1,19 +1,131 @@
 use serde::{ Deserialize, Serialize };
 use rig::{ tool::Tool, completion::ToolDefinition };
 use anyhow::Result;
+use std::str::FromStr;
 use tokio::process::Command;
 
-#[derive(Deserialize, Serialize)]
-pub struct ShellTool;
+use crate::tools::sandbox::ShellSandbox;
+
+/// Shell used to interpret the commands
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    Sh,
+    Bash,
+    PowerShell,
+    Cmd,
+}
+
+impl Shell {
+    /// PowerShell on Windows, sh everywhere else
+    pub fn detect() -> Self {
+        if cfg!(windows) { Shell::PowerShell } else { Shell::Sh }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shell::Sh => "sh",
+            Shell::Bash => "bash",
+            Shell::PowerShell => "powershell",
+            Shell::Cmd => "cmd",
+        }
+    }
+
+    /// A process running `command` in this shell
+    pub fn command(&self, command: &str) -> Command {
+        let (program, flag) = match self {
+            Shell::Sh => ("sh", "-c"),
+            Shell::Bash => ("bash", "-c"),
+            Shell::PowerShell => ("powershell", "-Command"),
+            Shell::Cmd => ("cmd", "/C"),
+        };
+        let mut process = Command::new(program);
+        process.arg(flag).arg(command);
+        process
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::detect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown shell: {0}")]
+pub struct UnknownShellError(String);
+
+impl FromStr for Shell {
+    type Err = UnknownShellError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sh" => Ok(Shell::Sh),
+            "bash" => Ok(Shell::Bash),
+            "powershell" | "pwsh" => Ok(Shell::PowerShell),
+            "cmd" => Ok(Shell::Cmd),
+            _ => Err(UnknownShellError(s.to_string())),
+        }
+    }
+}
+
+/// Runs commands in a configurable shell. Defaults to the OS shell and the process
+/// working directory.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ShellTool {
+    pub shell: Shell,
+    /// Directory the commands run in
+    pub working_dir: Option<String>,
+    /// Restrictions applied to every command, see `ShellSandbox`
+    pub sandbox: Option<ShellSandbox>,
+}
+
+impl ShellTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_shell(mut self, shell: Shell) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    pub fn with_working_dir(mut self, working_dir: &str) -> Self {
+        self.working_dir = Some(working_dir.to_string());
+        self
+    }
+
+    pub fn with_sandbox(mut self, sandbox: ShellSandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct ShellArgs {
     pub command: String,
 }
 
+/// Result of a command. A non-zero exit code is not an error, the model gets to see it.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ShellOutput {
+    /// `None` when the process was killed by a signal
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Output was cut at the sandbox's `max_output_bytes`
+    #[serde(default)]
+    pub truncated: bool,
+    /// The sandbox killed the command at its timeout
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ShellError {
     #[error("Failed to execute command: {0}")] ExecutionError(String),
+    #[error("Command rejected by the sandbox: {0}")] Denied(String),
+    #[error("Unknown shell session: {0}")] UnknownSession(String),
     #[error(transparent)] Other(#[from] anyhow::Error),
 }
 
@@ -24,12 +136,16 @@ impl Tool for ShellTool {
     const NAME: &'static str = "shell_tool";
     type Error = ShellError;
     type Args = ShellArgs;
-    type Output = String;
+    type Output = ShellOutput;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: "shell_tool".to_string(),
-            description: "Executes a shell command on **Windows** and returns the output.".to_string(),
+            description: format!(
+                "Executes a command with {} on {} and returns its exit code, stdout and stderr.",
+                self.shell.name(),
+                std::env::consts::OS
+            ),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -44,19 +160,107 @@ impl Tool for ShellTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        // Use powershell for Windows
-        let output = Command::new("powershell")
-            .arg("-Command")
-            .arg(&args.command)
+        if let Some(sandbox) = &self.sandbox {
+            let root = sandbox.root
+                .as_ref()
+                .or(self.working_dir.as_ref())
+                .ok_or_else(|| {
+                    ShellError::ExecutionError("the sandbox needs a root directory".to_string())
+                })?;
+            return sandbox.run(self.shell, &args.command, root).await;
+        }
+
+        let mut command = self.shell.command(&args.command);
+        if let Some(working_dir) = &self.working_dir {
+            command.current_dir(working_dir);
+        }
+        let output = command
             .output().await
             .map_err(|e| ShellError::ExecutionError(e.to_string()))?;
 
-        dbg!("Shell output {:?}", &output);
+        Ok(ShellOutput {
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            truncated: false,
+            timed_out: false,
+        })
+    }
+}
 
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            Err(ShellError::ExecutionError(String::from_utf8_lossy(&output.stderr).to_string()))
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shells_are_parsed_by_name() {
+        assert_eq!("bash".parse::<Shell>().unwrap(), Shell::Bash);
+        assert_eq!("PowerShell".parse::<Shell>().unwrap(), Shell::PowerShell);
+        assert_eq!("pwsh".parse::<Shell>().unwrap(), Shell::PowerShell);
+        assert!("zsh".parse::<Shell>().is_err());
+        assert_eq!(Shell::detect(), if cfg!(windows) { Shell::PowerShell } else { Shell::Sh });
+    }
+
+    #[test]
+    fn each_shell_runs_the_command_with_its_flag() {
+        let command_line = |shell: Shell| {
+            let command = shell.command("echo hi");
+            let command = command.as_std();
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|part| part.to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(command_line(Shell::Sh), ["sh", "-c", "echo hi"]);
+        assert_eq!(command_line(Shell::Bash), ["bash", "-c", "echo hi"]);
+        assert_eq!(command_line(Shell::PowerShell), ["powershell", "-Command", "echo hi"]);
+        assert_eq!(command_line(Shell::Cmd), ["cmd", "/C", "echo hi"]);
+    }
+
+    fn run(command: &str) -> ShellArgs {
+        ShellArgs { command: command.to_string() }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exit_codes_and_both_streams_are_reported() {
+        let tool = ShellTool::new().with_shell(Shell::Sh);
+
+        let output = tool.call(run("echo out; echo err >&2; exit 3")).await.unwrap();
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+
+        let output = tool.call(run("kill -9 $$")).await.unwrap();
+        assert_eq!(output.exit_code, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn commands_run_in_the_working_dir() {
+        let tool = ShellTool::new().with_shell(Shell::Sh).with_working_dir("/");
+        let output = tool.call(run("pwd")).await.unwrap();
+        assert_eq!(output.stdout, "/\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sandboxed_output_is_truncated() {
+        let root = std::env::temp_dir();
+        let sandbox = ShellSandbox::new()
+            .with_root(root.to_str().unwrap())
+            .with_max_output_bytes(100);
+        let tool = ShellTool::new().with_shell(Shell::Sh).with_sandbox(sandbox);
+
+        let lines = "i=0; while [ $i -lt 100 ]; do echo line $i; i=$((i+1)); done";
+        let output = tool.call(run(lines)).await.unwrap();
+        assert!(output.truncated);
+        assert!(output.stdout.len() <= 100, "{}", output.stdout.len());
+        assert!(output.stdout.starts_with("line 0\n"));
+        assert_eq!(output.exit_code, Some(0));
+
+        let output = tool.call(run("echo short")).await.unwrap();
+        assert!(!output.truncated);
+        assert_eq!(output.stdout, "short\n");
     }
 }
@@ -0,0 +1,113 @@
+use rig::{ completion::ToolDefinition, tool::{ ToolDyn, ToolError } };
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Tool {tool} timed out after {timeout:?}")]
+pub struct ToolTimeoutError {
+    pub tool: String,
+    pub timeout: Duration,
+}
+
+/// Wraps a tool so a call taking longer than `timeout` is abandoned. The model receives
+/// the timeout as a tool error and can decide what to do next.
+pub struct TimeoutTool {
+    inner: Box<dyn ToolDyn>,
+    timeout: Duration,
+}
+
+impl TimeoutTool {
+    pub fn new(inner: Box<dyn ToolDyn>, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+impl ToolDyn for TimeoutTool {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn definition<'a>(
+        &'a self,
+        prompt: String
+    ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + 'a>> {
+        self.inner.definition(prompt)
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: String
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + 'a>> {
+        Box::pin(async move {
+            match tokio::time::timeout(self.timeout, self.inner.call(args)).await {
+                Ok(result) => result,
+                Err(_) => {
+                    let error = ToolTimeoutError { tool: self.inner.name(), timeout: self.timeout };
+                    Err(ToolError::ToolCallError(Box::new(error)))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Tool that answers after `delay`
+    struct SlowTool {
+        delay: Duration,
+    }
+
+    impl ToolDyn for SlowTool {
+        fn name(&self) -> String {
+            "slow_tool".to_string()
+        }
+
+        fn definition<'a>(
+            &'a self,
+            _prompt: String
+        ) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + 'a>> {
+            Box::pin(async move {
+                ToolDefinition {
+                    name: self.name(),
+                    description: "Answers slowly".to_string(),
+                    parameters: serde_json::json!({ "type": "object", "properties": {} }),
+                }
+            })
+        }
+
+        fn call<'a>(
+            &'a self,
+            args: String
+        ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + 'a>> {
+            Box::pin(async move {
+                tokio::time::sleep(self.delay).await;
+                Ok(args)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_calls_return_a_timeout_error() {
+        let slow = Box::new(SlowTool { delay: Duration::from_secs(60) });
+        let tool = TimeoutTool::new(slow, Duration::from_millis(50));
+
+        let started = Instant::now();
+        let error = tool.call("{}".to_string()).await.unwrap_err();
+
+        assert!(error.to_string().contains("Tool slow_tool timed out after 50ms"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn fast_calls_pass_through() {
+        let fast = Box::new(SlowTool { delay: Duration::ZERO });
+        let tool = TimeoutTool::new(fast, Duration::from_secs(5));
+
+        assert_eq!(tool.name(), "slow_tool");
+        assert_eq!(tool.call("{}".to_string()).await.unwrap(), "{}");
+    }
+}
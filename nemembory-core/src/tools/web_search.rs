@@ -1,21 +1,72 @@
-use brave_rs::{ BraveClient, brave::BraveClientError };
 use rig::{ completion::ToolDefinition, tool::Tool };
 use serde::{ Deserialize, Serialize };
 use serde_json::json;
+use std::sync::Arc;
+use tracing::warn;
 
-#[derive(Debug, thiserror::Error)]
-#[error("Search error")]
-pub struct SearchError;
+use crate::tools::search_provider::{
+    BraveProvider,
+    Freshness,
+    SearchError,
+    SearchProvider,
+    SearchRequest,
+    WebSearchResult,
+};
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct WebSearchResult {
-    pub title: String,
-    pub url: String,
-    pub description: String,
+/// Most results the model can ask for in one search
+pub const MAX_SEARCH_RESULTS: usize = 20;
+
+/// Searches the web through a `SearchProvider`, Brave by default. `count`, `freshness` and
+/// `sites` are defaults the model can override per call.
+#[derive(Clone)]
+pub struct WebSearch {
+    provider: Arc<dyn SearchProvider>,
+    pub count: usize,
+    pub freshness: Option<Freshness>,
+    pub sites: Vec<String>,
+}
+
+impl Default for WebSearch {
+    fn default() -> Self {
+        Self {
+            provider: Arc::new(BraveProvider::from_env()),
+            count: 10,
+            freshness: None,
+            sites: Vec::new(),
+        }
+    }
+}
+
+impl WebSearch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_provider<P>(mut self, provider: P) -> Self where P: SearchProvider + 'static {
+        self.provider = Arc::new(provider);
+        self
+    }
+
+    pub fn with_count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub fn with_freshness(mut self, freshness: Freshness) -> Self {
+        self.freshness = Some(freshness);
+        self
+    }
+
+    /// Restricts every search to these domains unless the model passes its own
+    pub fn with_sites(mut self, sites: &[&str]) -> Self {
+        self.sites = sites
+            .iter()
+            .map(|site| site.to_string())
+            .collect();
+        self
+    }
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct WebSearch;
 impl Tool for WebSearch {
     const NAME: &'static str = "web_search";
     type Error = SearchError;
@@ -33,6 +84,21 @@ impl Tool for WebSearch {
                         "type": "string",
                         "description": "The query to search the web"
                     },
+                    "count": {
+                        "type": "integer",
+                        "description": format!("Number of results, {} by default", self.count),
+                        "maximum": MAX_SEARCH_RESULTS
+                    },
+                    "freshness": {
+                        "type": "string",
+                        "enum": ["day", "week", "month", "year"],
+                        "description": "Only return results from the past day, week, month or year"
+                    },
+                    "sites": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only return results from these domains, e.g. [\"docs.rs\"]"
+                    },
                 },
                 "required": ["query"]
             }),
@@ -40,44 +106,25 @@ impl Tool for WebSearch {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let api_key = std::env::var("BRAVE_API_KEY").expect("BRAVE_API_KEY not set");
-        let client = BraveClient::new(&api_key);
-        let result = client.web_search_by_query(&args.query).await;
-        match result {
-            Ok(response) => {
-                let search_result = match response.web {
-                    Some(web) =>
-                        web.results
-                            .iter()
-                            .map(|r| WebSearchResult {
-                                title: r.title.clone(),
-                                url: r.url.clone(),
-                                description: r.description.clone(),
-                            })
-                            .collect(),
-                    None => {
-                        eprintln!("WebSearch: no web results for query: {}", args.query);
-                        Vec::new()
-                    }
-                };
-
-                Ok(search_result)
-            }
-            Err(er) => {
-                eprintln!("Brave client error: {}", er.to_string());
-                Err(er.into())
-            }
-        }
-    }
-}
-
-impl From<BraveClientError> for SearchError {
-    fn from(_: BraveClientError) -> Self {
-        SearchError
+        let request = SearchRequest {
+            query: args.query,
+            count: args.count.unwrap_or(self.count).clamp(1, MAX_SEARCH_RESULTS),
+            freshness: args.freshness.or(self.freshness),
+            sites: args.sites.unwrap_or_else(|| self.sites.clone()),
+        };
+        self.provider.search(&request).await.inspect_err(|e| {
+            warn!(provider = self.provider.name(), "web search failed: {}", e);
+        })
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct WebSearchArgs {
-    query: String,
+    pub query: String,
+    #[serde(default)]
+    pub count: Option<usize>,
+    #[serde(default)]
+    pub freshness: Option<Freshness>,
+    #[serde(default)]
+    pub sites: Option<Vec<String>>,
 }
@@ -0,0 +1,795 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use nemembory_core::{
+    AgentPersistence,
+    InMemoryStore,
+    MemoryStore,
+    ModelProvider,
+    NememboryAgent,
+    ToolRegistry,
+};
+use nemembory_core::agent::agent::{ Message, MessageHandler, MessageRole };
+use nemembory_core::agent::{
+    AgentEvent,
+    AgentObserver,
+    CompactionConfig,
+    CompletionEvent,
+    LlmResponseHooks,
+    MockResponse,
+    MockRunnableAgent,
+    MockScript,
+    MockToolCall,
+    PriceTable,
+    RunUsage,
+    RunnableAgent,
+    TokenUsage,
+    ToolCall,
+    ToolCallEvent,
+    ToolDecision,
+    ToolResultEvent,
+    UsageTracker,
+};
+use nemembory_core::CancellationToken;
+use nemembory_core::agent::compaction::SUMMARY_PROMPT;
+use nemembory_core::data::{ Agent, DbError, Tool as StoredTool };
+use rig::agent::AgentBuilder;
+use rig::OneOrMany;
+use rig::completion::{
+    AssistantContent,
+    CompletionError,
+    CompletionModel,
+    CompletionRequest,
+    CompletionResponse,
+    GetTokenUsage,
+    ToolDefinition,
+    Usage,
+};
+use rig::tool::Tool;
+use rig::streaming::{ RawStreamingChoice, StreamingCompletionResponse, StreamingPrompt };
+use serde::{ Deserialize, Serialize };
+use std::io::ErrorKind;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::time::{ Duration, Instant };
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::net::TcpListener;
+
+/// Records every hook callback as a short line
+#[derive(Default)]
+struct RecordingObserver {
+    events: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl AgentObserver for RecordingObserver {
+    async fn on_tool_call(&self, event: &ToolCallEvent) {
+        let line = format!("call {} {} {}", event.agent_name, event.tool_name, event.args);
+        self.events.lock().unwrap().push(line);
+    }
+
+    async fn on_tool_result(&self, event: &ToolResultEvent) {
+        let line = format!("result {} {}", event.tool_name, event.result);
+        self.events.lock().unwrap().push(line);
+    }
+
+    async fn on_completion(&self, event: &CompletionEvent) {
+        let line = format!("completion {} {}", event.content, event.usage.total_tokens());
+        self.events.lock().unwrap().push(line);
+    }
+}
+
+#[derive(Default)]
+struct RecordingHandler {
+    messages: Mutex<Vec<Message>>,
+    usage: Mutex<Vec<RunUsage>>,
+}
+
+#[async_trait]
+impl MessageHandler for RecordingHandler {
+    async fn handle_message(&self, message: Message) -> Result<(), std::io::Error> {
+        self.messages.lock().unwrap().push(message);
+        Ok(())
+    }
+
+    async fn handle_usage(&self, usage: RunUsage) -> Result<(), std::io::Error> {
+        self.usage.lock().unwrap().push(usage);
+        Ok(())
+    }
+}
+
+fn weather_script() -> MockScript {
+    MockScript::new(
+        vec![
+            MockResponse::text("It is sunny in Toronto.")
+                .with_tool_call(
+                    MockToolCall::new("web_search", r#"{"query":"weather toronto"}"#, "Sunny")
+                )
+                .with_chunks(&["It is ", "sunny ", "in Toronto."])
+                .with_usage(100, 20),
+            MockResponse::text("You're welcome!")
+        ]
+    )
+}
+
+fn mock_agent(script: MockScript) -> NememboryAgent {
+    NememboryAgent::from_runnable("mock_agent", Box::new(MockRunnableAgent::new(script)))
+}
+
+#[tokio::test]
+async fn hooks_receive_tool_calls_results_and_completions() {
+    let observer = Arc::new(RecordingObserver::default());
+    let mut hooks = LlmResponseHooks::new();
+    hooks.add_observer(observer.clone());
+    let mut agent = mock_agent(weather_script()).with_hooks(hooks);
+
+    agent.run("What is the weather?", 5).await.unwrap();
+
+    assert_eq!(*observer.events.lock().unwrap(), [
+        r#"call mock_agent web_search {"query":"weather toronto"}"#,
+        "result web_search Sunny",
+        "completion It is sunny in Toronto. 120",
+    ]);
+}
+
+#[tokio::test]
+async fn tool_call_events_carry_the_arguments_a_policy_rewrote() {
+    let observer = Arc::new(RecordingObserver::default());
+    let mut hooks = LlmResponseHooks::new();
+    hooks.add_observer(observer.clone());
+    let mut agent = mock_agent(weather_script()).with_hooks(hooks);
+    agent.tool_policies.add(
+        Arc::new(|_: &ToolCall| ToolDecision::Rewrite(serde_json::json!({ "query": "rewritten" })))
+    );
+
+    agent.run("What is the weather?", 5).await.unwrap();
+
+    let events = observer.events.lock().unwrap();
+    assert_eq!(events[0], r#"call mock_agent web_search {"query":"rewritten"}"#);
+    assert_eq!(events[1], "result web_search Sunny");
+}
+
+#[tokio::test]
+async fn handlers_receive_every_message_and_the_run_usage() {
+    let handler = Arc::new(RecordingHandler::default());
+    let handlers: Vec<Arc<dyn MessageHandler + Send + Sync>> = vec![handler.clone()];
+    let mut agent = mock_agent(weather_script()).with_handlers(handlers);
+
+    agent.run("What is the weather?", 5).await.unwrap();
+    agent.run("Thanks", 5).await.unwrap();
+
+    let messages = handler.messages.lock().unwrap();
+    let messages: Vec<(&str, &str)> = messages
+        .iter()
+        .map(|m| {
+            let role = match m.role {
+                MessageRole::User => "user",
+                MessageRole::Assistant => "assistant",
+                MessageRole::Summary => "summary",
+            };
+            (role, m.message.as_str())
+        })
+        .collect();
+    assert_eq!(messages, [
+        ("user", "What is the weather?"),
+        ("assistant", "It is sunny in Toronto."),
+        ("user", "Thanks"),
+        ("assistant", "You're welcome!"),
+    ]);
+
+    let usage = handler.usage.lock().unwrap();
+    assert_eq!(usage.len(), 2);
+    assert_eq!(usage[0].agent_name, "mock_agent");
+    assert_eq!(usage[0].total.input_tokens, 100);
+    assert_eq!(usage[0].total.output_tokens, 20);
+}
+
+#[tokio::test]
+async fn scripted_responses_are_returned_in_order() {
+    let mock = MockRunnableAgent::new(weather_script());
+    let prompts = mock.prompt_log();
+    let mut agent = NememboryAgent::from_runnable("mock_agent", Box::new(mock));
+
+    assert_eq!(agent.run("What is the weather?", 5).await.unwrap(), "It is sunny in Toronto.");
+    assert_eq!(agent.run("Thanks", 5).await.unwrap(), "You're welcome!");
+    // The last response repeats once the script is exhausted
+    assert_eq!(agent.run("Bye", 5).await.unwrap(), "You're welcome!");
+
+    assert_eq!(*prompts.lock().unwrap(), ["What is the weather?", "Thanks", "Bye"]);
+    assert_eq!(agent.messages.len(), 6);
+}
+
+#[tokio::test]
+async fn runs_needing_more_turns_fail_and_are_not_recorded() {
+    let mut agent = mock_agent(weather_script());
+
+    assert!(agent.run("What is the weather?", 0).await.is_err());
+    assert!(agent.messages.is_empty());
+}
+
+#[tokio::test]
+async fn stream_yields_the_scripted_chunks() {
+    let mut agent = mock_agent(weather_script());
+
+    let chunks: Vec<String> = agent
+        .run_stream("What is the weather?", 5)
+        .map(|chunk| chunk.unwrap())
+        .collect().await;
+
+    assert_eq!(chunks, ["It is ", "sunny ", "in Toronto."]);
+    assert_eq!(agent.messages.len(), 2);
+    assert_eq!(agent.messages[1].message, "It is sunny in Toronto.");
+}
+
+#[tokio::test]
+async fn events_report_tool_activity_before_the_text() {
+    let mut agent = mock_agent(weather_script());
+
+    let events: Vec<AgentEvent> = agent.run_events("What is the weather?", 5).collect().await;
+
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|event| {
+            match event {
+                AgentEvent::ToolCallStarted { .. } => "tool_call",
+                AgentEvent::ToolResult { .. } => "tool_result",
+                AgentEvent::TextDelta { .. } => "text",
+                AgentEvent::Usage { .. } => "usage",
+                AgentEvent::Final { .. } => "final",
+                _ => "other",
+            }
+        })
+        .collect();
+    assert_eq!(kinds, ["tool_call", "tool_result", "text", "text", "text", "usage", "final"]);
+    let Some(AgentEvent::Final { response }) = events.last() else {
+        panic!("the last event is the final response");
+    };
+    assert_eq!(response, "It is sunny in Toronto.");
+}
+
+#[tokio::test]
+async fn memories_are_recalled_by_later_sessions_of_the_same_agent() {
+    let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::new());
+    let mut first = mock_agent(MockScript::default()).with_memory(store.clone(), 5);
+    first.run("My favourite colour is green.", 5).await.unwrap();
+
+    let mock = MockRunnableAgent::new(MockScript::default());
+    let prompts = mock.prompt_log();
+    let mut second = NememboryAgent::from_runnable("mock_agent", Box::new(mock))
+        .with_memory(store.clone(), 5);
+    second.run("What is my favourite colour?", 5).await.unwrap();
+
+    let prompt = prompts.lock().unwrap()[0].clone();
+    assert!(prompt.starts_with("# Relevant memories from previous conversations:\n"));
+    assert!(prompt.contains("- My favourite colour is green.\n"));
+    assert!(prompt.ends_with("# User request:\nWhat is my favourite colour?"));
+    // The history keeps the prompt the user typed
+    assert_eq!(second.messages[0].message, "What is my favourite colour?");
+
+    let mock = MockRunnableAgent::new(MockScript::default());
+    let prompts = mock.prompt_log();
+    let mut other = NememboryAgent::from_runnable("other_agent", Box::new(mock))
+        .with_memory(store, 5);
+    other.run("What is my favourite colour?", 5).await.unwrap();
+    assert_eq!(prompts.lock().unwrap()[0], "What is my favourite colour?");
+}
+
+fn slow_tool_script() -> MockScript {
+    MockScript::new(
+        vec![
+            MockResponse::text("Done.").with_tool_call(
+                MockToolCall::new("shell_tool", r#"{"command":"sleep 60"}"#, "").with_delay(
+                    Duration::from_secs(60)
+                )
+            )
+        ]
+    )
+}
+
+#[tokio::test]
+async fn cancelling_stops_a_run_waiting_on_a_tool() {
+    let mut agent = mock_agent(slow_tool_script());
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        trigger.cancel();
+    });
+
+    let started = Instant::now();
+    let error = agent.run_with_cancel("Run it", 5, cancel).await.unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::Interrupted);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(agent.messages.is_empty());
+}
+
+#[tokio::test]
+async fn cancelling_ends_the_event_stream_with_an_error() {
+    let mut agent = mock_agent(slow_tool_script());
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        trigger.cancel();
+    });
+
+    let events: Vec<AgentEvent> = agent.run_events_with_cancel("Run it", 5, cancel).collect().await;
+
+    assert!(matches!(events.first(), Some(AgentEvent::ToolCallStarted { .. })));
+    let Some(AgentEvent::Error { message }) = events.last() else {
+        panic!("the stream ends with an error");
+    };
+    assert!(message.contains("cancelled"), "{}", message);
+    assert!(agent.messages.is_empty());
+}
+
+#[tokio::test]
+async fn runs_exceeding_the_run_timeout_fail() {
+    let mut agent = mock_agent(slow_tool_script()).with_run_timeout(Duration::from_millis(50));
+
+    let started = Instant::now();
+    let error = agent.run("Run it", 5).await.unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(agent.messages.is_empty());
+
+    let events: Vec<AgentEvent> = agent.run_events("Run it", 5).collect().await;
+    let Some(AgentEvent::Error { message }) = events.last() else {
+        panic!("the stream ends with an error");
+    };
+    assert!(message.contains("timed out"), "{}", message);
+}
+
+/// Token usage a `ScriptedModel` reports at the end of each streamed turn
+#[derive(Clone, Serialize, Deserialize)]
+struct ScriptedUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl GetTokenUsage for ScriptedUsage {
+    fn token_usage(&self) -> Option<Usage> {
+        let mut usage = Usage::new();
+        usage.input_tokens = self.input_tokens;
+        usage.output_tokens = self.output_tokens;
+        usage.total_tokens = self.input_tokens + self.output_tokens;
+        Some(usage)
+    }
+}
+
+/// rig model that streams `tool_turns` turns calling the weather tool and then a text turn
+#[derive(Clone, Default)]
+struct ScriptedModel {
+    tool_turns: usize,
+    turns: Arc<AtomicUsize>,
+}
+
+impl ScriptedModel {
+    fn new(tool_turns: usize) -> Self {
+        Self { tool_turns, turns: Arc::new(AtomicUsize::new(0)) }
+    }
+}
+
+/// rig tool the `ScriptedModel` calls
+#[derive(Deserialize, Serialize)]
+struct Weather;
+
+impl Tool for Weather {
+    const NAME: &'static str = "weather";
+    type Error = std::io::Error;
+    type Args = serde_json::Value;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Returns the weather".to_string(),
+            parameters: serde_json::json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+        Ok("Sunny".to_string())
+    }
+}
+
+fn scripted_agent(tool_turns: usize) -> rig::agent::Agent<ScriptedModel> {
+    AgentBuilder::new(ScriptedModel::new(tool_turns)).tool(Weather).build()
+}
+
+impl CompletionModel for ScriptedModel {
+    type Response = ();
+    type StreamingResponse = ScriptedUsage;
+    type Client = ();
+
+    fn make(_client: &Self::Client, _model: impl Into<String>) -> Self {
+        Self::default()
+    }
+
+    /// Only summaries, which go without a preamble or tools, are completed without streaming
+    async fn completion(
+        &self,
+        request: CompletionRequest
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        if request.preamble.is_some() || !request.tools.is_empty() {
+            return Err(CompletionError::ProviderError("not a summary request".to_string()));
+        }
+        let mut usage = Usage::new();
+        usage.input_tokens = 30;
+        usage.output_tokens = 3;
+        let summary = format!("Summary of {} messages.", request.chat_history.len() - 1);
+        Ok(CompletionResponse {
+            choice: OneOrMany::one(AssistantContent::text(summary)),
+            usage,
+            raw_response: (),
+        })
+    }
+
+    async fn stream(
+        &self,
+        _request: CompletionRequest
+    ) -> Result<StreamingCompletionResponse<ScriptedUsage>, CompletionError> {
+        let turn = self.turns.fetch_add(1, Ordering::SeqCst);
+        let choices = if turn < self.tool_turns {
+            vec![
+                RawStreamingChoice::ToolCall {
+                    id: format!("call-{}", turn),
+                    call_id: None,
+                    name: Weather::NAME.to_string(),
+                    arguments: serde_json::json!({}),
+                },
+                RawStreamingChoice::FinalResponse(ScriptedUsage {
+                    input_tokens: 50,
+                    output_tokens: 5,
+                })
+            ]
+        } else {
+            vec![
+                RawStreamingChoice::Message("It is ".to_string()),
+                RawStreamingChoice::Message("sunny.".to_string()),
+                RawStreamingChoice::FinalResponse(ScriptedUsage {
+                    input_tokens: 100,
+                    output_tokens: 20,
+                })
+            ]
+        };
+        let stream = futures::stream::iter(choices.into_iter().map(Ok));
+        Ok(StreamingCompletionResponse::stream(Box::pin(stream)))
+    }
+}
+
+#[tokio::test]
+async fn streamed_turns_report_their_usage_and_completion() {
+    let observer = Arc::new(RecordingObserver::default());
+    let mut hooks = LlmResponseHooks::new();
+    hooks.add_observer(observer.clone());
+    let handler = Arc::new(RecordingHandler::default());
+    let handlers: Vec<Arc<dyn MessageHandler + Send + Sync>> = vec![handler.clone()];
+    let mut agent = NememboryAgent::from_runnable("rig_agent", Box::new(scripted_agent(1)))
+        .with_hooks(hooks)
+        .with_handlers(handlers);
+
+    let events: Vec<AgentEvent> = agent.run_events("What is the weather?", 5).collect().await;
+
+    assert!(
+        events.iter().any(|event| {
+            matches!(event, AgentEvent::Usage { input_tokens: 150, output_tokens: 25 })
+        })
+    );
+    let events = observer.events.lock().unwrap();
+    assert_eq!(*events, [
+        "call rig_agent weather {}",
+        r#"result weather "Sunny""#,
+        "completion It is sunny. 120",
+    ]);
+    // The text turn is recorded as it finishes, the tool call turn from the run total
+    let usage = handler.usage.lock().unwrap();
+    assert_eq!(usage[0].turns, [TokenUsage::new(100, 20), TokenUsage::new(50, 5)]);
+    assert_eq!(usage[0].total, TokenUsage::new(150, 25));
+}
+
+#[tokio::test]
+async fn streamed_mock_runs_report_the_completion_and_count_usage_once() {
+    let observer = Arc::new(RecordingObserver::default());
+    let mut hooks = LlmResponseHooks::new();
+    hooks.add_observer(observer.clone());
+    let handler = Arc::new(RecordingHandler::default());
+    let handlers: Vec<Arc<dyn MessageHandler + Send + Sync>> = vec![handler.clone()];
+    let mut agent = mock_agent(weather_script()).with_hooks(hooks).with_handlers(handlers);
+
+    let _: Vec<AgentEvent> = agent.run_events("What is the weather?", 5).collect().await;
+
+    let events = observer.events.lock().unwrap();
+    assert_eq!(events.last().unwrap(), "completion It is sunny in Toronto. 120");
+    let usage = handler.usage.lock().unwrap();
+    assert_eq!(usage[0].turns, [TokenUsage::new(100, 20)]);
+}
+
+#[tokio::test]
+async fn hooks_report_completions_of_rig_streams() {
+    let observer = Arc::new(RecordingObserver::default());
+    let mut hooks = LlmResponseHooks::new();
+    hooks.add_observer(observer.clone());
+    let usage = UsageTracker::new();
+    hooks.set_usage_tracker(usage.clone());
+    let agent = scripted_agent(1);
+
+    let mut stream = agent
+        .stream_prompt("What is the weather?")
+        .with_history(vec![rig::message::Message::user("Hello")])
+        .with_hook(hooks)
+        .multi_turn(5).await;
+    while stream.next().await.is_some() {}
+
+    let events = observer.events.lock().unwrap();
+    assert_eq!(events.last().unwrap(), "completion It is sunny. 120");
+    assert_eq!(usage.finish_run("rig_agent", "scripted", &PriceTable::new()).turns, [
+        TokenUsage::new(100, 20),
+    ]);
+}
+
+#[tokio::test]
+async fn rig_streams_run_the_tools_between_turns() {
+    let mut agent = NememboryAgent::from_runnable("rig_agent", Box::new(scripted_agent(2)));
+
+    let events: Vec<AgentEvent> = agent.run_events("What is the weather?", 5).collect().await;
+
+    let kinds: Vec<String> = events
+        .iter()
+        .map(|event| {
+            match event {
+                AgentEvent::ToolCallStarted { id, name, .. } => format!("call {} {}", id, name),
+                AgentEvent::ToolResult { id, result } => format!("result {} {}", id, result),
+                AgentEvent::TextDelta { text } => format!("text {}", text),
+                AgentEvent::Usage { input_tokens, output_tokens } => {
+                    format!("usage {} {}", input_tokens, output_tokens)
+                }
+                AgentEvent::Final { response } => format!("final {}", response),
+                other => format!("{:?}", other),
+            }
+        })
+        .collect();
+    assert_eq!(kinds, [
+        "call call-0 weather",
+        r#"result call-0 "Sunny""#,
+        "call call-1 weather",
+        r#"result call-1 "Sunny""#,
+        "text It is ",
+        "text sunny.",
+        "usage 200 30",
+        "final It is sunny.",
+    ]);
+    assert_eq!(agent.messages[1].message, "It is sunny.");
+}
+
+#[tokio::test]
+async fn rig_streams_stop_after_max_turns() {
+    let mut agent = NememboryAgent::from_runnable("rig_agent", Box::new(scripted_agent(10)));
+
+    let events: Vec<AgentEvent> = agent.run_events("What is the weather?", 2).collect().await;
+
+    let calls = events
+        .iter()
+        .filter(|event| matches!(event, AgentEvent::ToolCallStarted { .. }))
+        .count();
+    // rig allows up to `max_turns + 2` model turns, streamed or not
+    assert_eq!(calls, 4);
+    let Some(AgentEvent::Error { message }) = events.last() else {
+        panic!("the stream ends with an error");
+    };
+    assert!(message.contains("MaxDepthError"), "{}", message);
+    assert!(agent.messages.is_empty());
+}
+
+#[tokio::test]
+async fn compaction_summarizes_without_tools_and_reports_its_own_usage() {
+    let observer = Arc::new(RecordingObserver::default());
+    let mut hooks = LlmResponseHooks::new();
+    hooks.add_observer(observer.clone());
+    let handler = Arc::new(RecordingHandler::default());
+    let handlers: Vec<Arc<dyn MessageHandler + Send + Sync>> = vec![handler.clone()];
+    let mock = MockRunnableAgent::new(
+        MockScript::new(
+            vec![
+                MockResponse::text("First answer.").with_usage(10, 1),
+                MockResponse::text("Second answer.").with_usage(10, 1),
+                MockResponse::text("The user asked three times.")
+                    .with_tool_call(MockToolCall::new("shell_tool", r#"{"command":"rm x"}"#, ""))
+                    .with_usage(30, 3),
+                MockResponse::text("Third answer.").with_usage(10, 1)
+            ]
+        )
+    );
+    let prompts = mock.prompt_log();
+    let mut agent = NememboryAgent::from_runnable("mock_agent", Box::new(mock))
+        .with_hooks(hooks)
+        .with_handlers(handlers)
+        .with_compaction(CompactionConfig::new(0).keep_recent(2));
+
+    agent.run("First", 5).await.unwrap();
+    agent.run("Second", 5).await.unwrap();
+    agent.run("Third", 5).await.unwrap();
+
+    assert_eq!(prompts.lock().unwrap()[2], SUMMARY_PROMPT);
+    assert!(matches!(agent.messages[0].role, MessageRole::Summary));
+    assert_eq!(agent.messages[0].message, "The user asked three times.");
+    let texts: Vec<&str> = agent.messages[1..]
+        .iter()
+        .map(|m| m.message.as_str())
+        .collect();
+    assert_eq!(texts, ["Second", "Second answer.", "Third", "Third answer."]);
+    // The summary's scripted tool call is never made
+    assert!(observer.events.lock().unwrap().iter().all(|event| !event.starts_with("call")));
+    let usage = handler.usage.lock().unwrap();
+    let totals: Vec<TokenUsage> = usage
+        .iter()
+        .map(|run| run.total)
+        .collect();
+    assert_eq!(totals, [
+        TokenUsage::new(10, 1),
+        TokenUsage::new(10, 1),
+        TokenUsage::new(30, 3),
+        TokenUsage::new(10, 1),
+    ]);
+}
+
+#[tokio::test]
+async fn rig_summaries_go_without_the_preamble_and_tools() {
+    let agent = AgentBuilder::new(ScriptedModel::new(1))
+        .preamble("You are a weather agent.")
+        .tool(Weather)
+        .build();
+    let history = vec![
+        rig::message::Message::user("What is the weather?"),
+        rig::message::Message::assistant("It is sunny.")
+    ];
+
+    let (summary, usage) = agent.summarize(&history).await.unwrap();
+
+    assert_eq!(summary, "Summary of 2 messages.");
+    assert_eq!(usage, TokenUsage::new(30, 3));
+}
+
+/// One stored agent and the tools granted to it
+struct StoredAgent {
+    agent: Agent,
+    tools: Vec<&'static str>,
+}
+
+#[async_trait]
+impl AgentPersistence for StoredAgent {
+    async fn save_agent(&self, _agent: Agent) -> Result<(), DbError> {
+        unimplemented!()
+    }
+
+    async fn load_agents(&self) -> Result<Vec<Agent>, DbError> {
+        Ok(vec![self.agent.clone()])
+    }
+
+    async fn get_agent(&self, _id: i8) -> Result<Agent, DbError> {
+        Ok(self.agent.clone())
+    }
+
+    async fn get_agent_by_code(&self, code: &str) -> Result<Agent, DbError> {
+        assert_eq!(code, self.agent.code);
+        Ok(self.agent.clone())
+    }
+
+    async fn get_agent_tools(&self, _code: &str) -> Result<Vec<StoredTool>, DbError> {
+        let tools = self.tools.iter().map(|name| StoredTool {
+            name: name.to_string(),
+            description: String::new(),
+        });
+        Ok(tools.collect())
+    }
+
+    async fn save_tool(&self, _tool: StoredTool) -> Result<(), DbError> {
+        unimplemented!()
+    }
+
+    async fn load_tools(&self) -> Result<Vec<StoredTool>, DbError> {
+        unimplemented!()
+    }
+
+    async fn grant_tool(&self, _code: &str, _tool_name: &str) -> Result<(), DbError> {
+        unimplemented!()
+    }
+
+    async fn revoke_tool(&self, _code: &str, _tool_name: &str) -> Result<(), DbError> {
+        unimplemented!()
+    }
+
+    async fn add_agent(&self, _agent: Agent) -> Result<Agent, DbError> {
+        unimplemented!()
+    }
+}
+
+/// Answers every Ollama chat request with "Hello" and records the request bodies
+async fn serve_ollama() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            let body = loop {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => break None,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+                let text = String::from_utf8_lossy(&request).to_string();
+                let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                    continue;
+                };
+                let length = head
+                    .to_lowercase()
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length: ")?.parse().ok())
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break Some(body.to_string());
+                }
+            };
+            let Some(body) = body else {
+                continue;
+            };
+            recorded.lock().unwrap().push(serde_json::from_str(&body).unwrap());
+            let response = serde_json::json!({
+                "model": "llama-test",
+                "created_at": "2026-01-01T00:00:00Z",
+                "message": { "role": "assistant", "content": "Hello" },
+                "done": true,
+            }).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    (base_url, requests)
+}
+
+#[tokio::test]
+async fn stored_agents_get_their_model_preamble_and_granted_tools() {
+    let (base_url, requests) = serve_ollama().await;
+    let model = || ModelProvider::Ollama { base_url: base_url.clone(), model: "llama-test".into() };
+    let agent = Agent {
+        id: 1,
+        code: "helper".to_string(),
+        display_name: "Helper".to_string(),
+        system_prompt: "You help with dates.".to_string(),
+    };
+    let registry = ToolRegistry::with_builtin_tools();
+    let store = StoredAgent { agent: agent.clone(), tools: vec!["get_date"] };
+
+    let mut stored = NememboryAgent::from_stored_agent(&store, "helper", model(), &registry).await
+        .unwrap();
+    assert_eq!(stored.name, "helper");
+    assert_eq!(stored.model, "llama-test");
+    assert_eq!(stored.run("Hi", 1).await.unwrap(), "Hello");
+
+    let store = StoredAgent { agent, tools: vec![] };
+    let mut stored = NememboryAgent::from_stored_agent(&store, "helper", model(), &registry).await
+        .unwrap();
+    stored.run("Hi", 1).await.unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests[0]["model"], "llama-test");
+    assert_eq!(requests[0]["messages"][0]["role"], "system");
+    assert_eq!(requests[0]["messages"][0]["content"], "You help with dates.");
+    let tools: Vec<_> = requests[0]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["function"]["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(tools, ["get_date"]);
+    // An agent without grants gets no tools
+    assert!(requests[1].get("tools").is_none());
+}
@@ -1,25 +1,100 @@
 use axum::{
     Router,
     extract::{ State, WebSocketUpgrade },
+    http::StatusCode,
     response::IntoResponse,
     routing::{ get, post },
     Json,
 };
 use tokio::sync::Mutex;
 use clap::Parser;
-use nemembory_core::{ ModelProvider, NememboryAgent };
+use nemembory_core::{
+    CancellationToken,
+    ConversationStore,
+    DbAgentStore,
+    ModelProvider,
+    NememboryAgent,
+    RestApiTool,
+    ToolRegistry,
+};
+use nemembory_core::agent::{
+    ApprovalPolicy,
+    ApprovalRequest,
+    ModelError,
+    PendingApproval,
+    ToolPolicies,
+    UsageTracker,
+};
+use nemembory_core::tools::Credentials;
 use tokio::net::TcpListener;
 use futures_util::{ SinkExt, StreamExt };
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
-type AppState = Arc<Mutex<SessionContext>>;
+#[derive(Clone)]
+struct AppState {
+    session: Arc<Mutex<SessionContext>>,
+    /// Shared with the session agent; websocket clients connect to it to approve tool calls
+    approval: Option<ApprovalPolicy>,
+}
 
 struct SessionContext {
-    agent: NememboryAgent,
+    /// Locked by the running prompt only, so the session stays readable during a run
+    agent: Arc<Mutex<NememboryAgent>>,
+    /// Usage of the session agent, shared with it
+    usage: UsageTracker,
     task: String,
     model: ModelProvider,
+    store: Option<Arc<DbAgentStore>>,
+    agent_code: Option<String>,
+    settings: AgentSettings,
+}
+
+/// Options applied to every agent the session builds
+#[derive(Clone)]
+struct AgentSettings {
+    run_timeout: Option<Duration>,
+    approval: Option<ApprovalPolicy>,
+    credentials: Credentials,
+}
+
+impl AgentSettings {
+    fn policies(&self) -> ToolPolicies {
+        let mut policies = ToolPolicies::new();
+        if let Some(approval) = &self.approval {
+            policies.add(Arc::new(approval.clone()));
+        }
+        policies
+    }
+
+    /// The built-in tools, with `rest_api` authenticating through the credential profiles
+    fn registry(&self) -> ToolRegistry {
+        let mut registry = ToolRegistry::with_builtin_tools();
+        registry.register_tool(RestApiTool::new().with_credentials(self.credentials.clone()));
+        registry
+    }
+}
+
+/// Frames sent by `/ws` clients as JSON; any other text is a prompt
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    ApprovalResponse {
+        id: u64,
+        approved: bool,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+}
+
+/// Frames sent to `/ws` clients as JSON; agent replies are sent as plain text
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerFrame {
+    ApprovalRequest(ApprovalRequest),
 }
 
 #[derive(Parser, Debug)]
@@ -30,6 +105,26 @@ struct Args {
 
     #[arg(short, long)]
     task: String,
+
+    /// Postgres connection string used to persist and resume conversations
+    #[arg(long)]
+    database_url: Option<String>,
+
+    /// Code of a stored agent to serve; its system prompt and granted tools replace the task
+    #[arg(long, requires = "database_url")]
+    agent: Option<String>,
+
+    /// Abort a prompt after this many seconds
+    #[arg(long)]
+    run_timeout: Option<u64>,
+
+    /// Ask the websocket client to approve shell commands and non-GET rest_api calls
+    #[arg(long)]
+    approve_tools: bool,
+
+    /// JSON file of credential profiles `rest_api` can authenticate with
+    #[arg(long)]
+    credentials: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -42,26 +137,52 @@ struct UpdateSessionRequest {
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    let model = match args.model.to_lowercase().as_str() {
-        "anthropic" => ModelProvider::Anthropic,
-        "gemini" => ModelProvider::Gemini,
-        other => {
-            eprintln!("Invalid model provider: {other}. Use 'anthropic' or 'gemini'.");
+    let model = match args.model.parse::<ModelProvider>() {
+        Ok(model) => model,
+        Err(e) => {
+            eprintln!(
+                "{e}. Use 'anthropic', 'gemini', 'openrouter:<model>', 'openai:<model>', \
+                'ollama:<model>' or 'openai-compatible:<model>'."
+            );
             std::process::exit(1);
         }
     };
 
     let task = args.task;
 
-    let agent = NememboryAgent::new("server_agent", task.clone(), model.clone()).default_handlers();
+    let store = match &args.database_url {
+        Some(url) => Some(Arc::new(DbAgentStore::new(url).await?)),
+        None => None,
+    };
 
-    let session_ctx: AppState = Arc::new(
-        Mutex::new(SessionContext {
-            agent,
-            task,
-            model,
-        })
-    );
+    let credentials = match &args.credentials {
+        Some(path) => Credentials::from_file(path)?,
+        None => Credentials::new(),
+    };
+    let settings = AgentSettings {
+        run_timeout: args.run_timeout.map(Duration::from_secs),
+        approval: args.approve_tools.then(|| {
+            ApprovalPolicy::sensitive_tools().with_timeout(Duration::from_secs(300))
+        }),
+        credentials,
+    };
+    let agent = build_agent(&task, &model, &store, &args.agent, &settings, true).await?;
+    let usage = agent.usage.clone();
+
+    let session_ctx = AppState {
+        approval: settings.approval.clone(),
+        session: Arc::new(
+            Mutex::new(SessionContext {
+                agent: Arc::new(Mutex::new(agent)),
+                usage,
+                task,
+                model,
+                store,
+                agent_code: args.agent,
+                settings,
+            })
+        ),
+    };
 
     let app = Router::new()
         .route("/ws", get(ws_handler))
@@ -78,65 +199,218 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Creates the session agent. With a database, the latest conversation is resumed when
+/// `resume` is set, otherwise a new conversation is started.
+async fn build_agent(
+    task: &str,
+    model: &ModelProvider,
+    store: &Option<Arc<DbAgentStore>>,
+    agent_code: &Option<String>,
+    settings: &AgentSettings,
+    resume: bool
+) -> Result<NememboryAgent> {
+    let Some(store) = store else {
+        let agent = new_agent(task, model, settings)?;
+        return Ok(with_run_timeout(agent, settings).default_handlers());
+    };
+
+    let agent = match agent_code {
+        Some(code) =>
+            NememboryAgent::from_stored_agent_with_policies(
+                store.as_ref(),
+                code,
+                model.clone(),
+                &settings.registry(),
+                settings.policies()
+            ).await?.with_credentials(settings.credentials.clone()),
+        None => new_agent(task, model, settings)?,
+    };
+    let agent = with_run_timeout(agent, settings).default_handlers();
+
+    let conversation = match store.latest_conversation(&agent.name).await? {
+        Some(conversation) if resume => conversation,
+        _ => store.create_conversation(&agent.name).await?,
+    };
+    println!("Using conversation {}", conversation.id);
+
+    Ok(agent.resume_conversation(store.clone(), conversation.id).await?)
+}
+
+fn new_agent(
+    task: &str,
+    model: &ModelProvider,
+    settings: &AgentSettings
+) -> Result<NememboryAgent, ModelError> {
+    let mut builder = NememboryAgent::builder("server_agent", model.clone())
+        .task(task)
+        .credentials(settings.credentials.clone());
+    if let Some(approval) = &settings.approval {
+        builder = builder.policy(Arc::new(approval.clone()));
+    }
+    builder.build()
+}
+
+fn with_run_timeout(agent: NememboryAgent, settings: &AgentSettings) -> NememboryAgent {
+    match settings.run_timeout {
+        Some(timeout) => agent.with_run_timeout(timeout),
+        None => agent,
+    }
+}
+
 async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
     ws.on_upgrade(move |socket| handle_websocket(socket, state))
 }
 
-async fn handle_websocket(socket: axum::extract::ws::WebSocket, context: AppState) {
-    let (mut sender, mut receiver) = socket.split();
+/// Runs each prompt in its own task so the socket keeps reading while the agent works.
+/// A `/stop` message cancels every prompt started on the socket, and so does closing it.
+/// With `--approve-tools`, sensitive tool calls are sent as `approval_request` frames to
+/// every connected socket and wait for the first matching `approval_response`.
+async fn handle_websocket(socket: axum::extract::ws::WebSocket, state: AppState) {
+    let (sender, mut receiver) = socket.split();
+    let sender = Arc::new(Mutex::new(sender));
+    // Cancelling the connection token also cancels every run started on this socket
+    let connection = CancellationToken::new();
+    // Shared by the runs started since the last `/stop`
+    let mut runs = connection.child_token();
+    let pending = Arc::new(Mutex::new(HashMap::<u64, PendingApproval>::new()));
+
+    let approver = state.approval.as_ref().map(|approval| {
+        let approver = approval.connect();
+        let mut requests = approver.requests;
+        let pending = pending.clone();
+        let sender = sender.clone();
+        let forwarder = tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                let frame = ServerFrame::ApprovalRequest(request.request.clone());
+                let Ok(frame) = serde_json::to_string(&frame) else {
+                    continue;
+                };
+                let id = request.request.id;
+                let settled = request.settled();
+                pending.lock().await.insert(id, request);
+                // Forget the request once another socket answered it or it timed out
+                let pending = pending.clone();
+                tokio::spawn(async move {
+                    settled.await;
+                    pending.lock().await.remove(&id);
+                });
+                let _ = sender
+                    .lock().await
+                    .send(axum::extract::ws::Message::Text(frame.into())).await;
+            }
+        });
+        (approver.id, forwarder)
+    });
 
     while let Some(Ok(msg)) = receiver.next().await {
         if let axum::extract::ws::Message::Text(text) = msg {
-            let mut ctx = context.lock().await;
-            if let Ok(response) = ctx.agent.run(&text, 4).await {
-                let _ = sender.send(axum::extract::ws::Message::Text(response.into())).await;
+            if text.trim() == "/stop" {
+                runs.cancel();
+                runs = connection.child_token();
+                continue;
+            }
+
+            if let Ok(frame) = serde_json::from_str::<ClientFrame>(&text) {
+                let ClientFrame::ApprovalResponse { id, approved, reason } = frame;
+                let reason = reason.unwrap_or_else(|| "rejected by the user".to_string());
+                match pending.lock().await.remove(&id) {
+                    Some(request) if approved => request.approve(),
+                    Some(request) => request.reject(&reason),
+                    None => eprintln!("No pending approval with id {}", id),
+                }
+                continue;
             }
+
+            let cancel = runs.clone();
+            let agent = state.session.lock().await.agent.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let mut agent = agent.lock().await;
+                let reply = match agent.run_with_cancel(&text, 4, cancel).await {
+                    Ok(response) => response,
+                    Err(e) => e.to_string(),
+                };
+                let _ = sender
+                    .lock().await
+                    .send(axum::extract::ws::Message::Text(reply.into())).await;
+            });
         }
     }
+
+    connection.cancel();
+    // Unanswered requests are denied once no approver holds them anymore
+    if let (Some(approval), Some((id, forwarder))) = (&state.approval, approver) {
+        approval.disconnect(id);
+        forwarder.abort();
+    }
+    pending.lock().await.clear();
 }
 
 async fn update_session(
     State(state): State<AppState>,
     Json(payload): Json<UpdateSessionRequest>
-) -> Json<serde_json::Value> {
-    let mut ctx = state.lock().await;
-
-    if let Some(model_str) = &payload.model {
-        let new_model = match model_str.to_lowercase().as_str() {
-            "anthropic" => Some(ModelProvider::Anthropic),
-            "gemini" => Some(ModelProvider::Gemini),
-            _ => None,
-        };
-        if let Some(model) = new_model {
-            ctx.model = model;
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let model = match payload.model.as_ref().map(|m| m.parse::<ModelProvider>()) {
+        Some(Ok(model)) => Some(model),
+        Some(Err(e)) => {
+            let error = serde_json::json!({ "status": "error", "error": e.to_string() });
+            return Err((StatusCode::BAD_REQUEST, Json(error)));
         }
+        None => None,
+    };
+
+    let mut ctx = state.session.lock().await;
+    if let Some(model) = model {
+        ctx.model = model;
     }
 
     if let Some(task) = payload.task {
         ctx.task = task;
     }
 
-    ctx.agent = NememboryAgent::new(
-        "server_agent",
-        ctx.task.clone(),
-        ctx.model.clone()
-    ).default_handlers();
+    match
+        build_agent(
+            &ctx.task,
+            &ctx.model,
+            &ctx.store,
+            &ctx.agent_code,
+            &ctx.settings,
+            false
+        ).await
+    {
+        Ok(agent) => {
+            ctx.usage = agent.usage.clone();
+            ctx.agent = Arc::new(Mutex::new(agent));
+        }
+        Err(e) => {
+            let error = serde_json::json!({ "status": "error", "error": e.to_string() });
+            return Ok(Json(error));
+        }
+    }
 
-    Json(
-        serde_json::json!({
-        "status": "ok",
-        "task": ctx.task,
-        "model": format!("{:?}", ctx.model)
-    })
+    Ok(
+        Json(
+            serde_json::json!({
+            "status": "ok",
+            "task": ctx.task,
+            "model": format!("{:?}", ctx.model)
+        })
+        )
     )
 }
 
 async fn get_session(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let ctx = state.lock().await;
+    let ctx = state.session.lock().await;
+    let usage = ctx.usage.session_usage();
     Json(
         serde_json::json!({
         "task": ctx.task,
-        "model": format!("{:?}", ctx.model)
+        "model": format!("{:?}", ctx.model),
+        "usage": {
+            "input_tokens": usage.input_tokens,
+            "output_tokens": usage.output_tokens,
+            "cost": ctx.usage.session_cost()
+        }
     })
     )
 }
@@ -1,8 +1,4 @@
-use std::sync::Arc;
-
 use clap::{ Parser };
-use nemembory_core::{ ModelProvider, agent::{ agent::NememboryAgent } };
-use tokio::sync::Mutex;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]